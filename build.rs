@@ -4,6 +4,10 @@ use std::path::PathBuf;
 use copy_to_output::copy_to_output_path;
 
 fn main() {
+    if env::var_os("CARGO_FEATURE_RUNTIME").is_none() {
+        return;
+    }
+
     let deps_path = PathBuf::from("deps")
         .canonicalize()
         .expect("Couldn't find deps directory");
@@ -0,0 +1,371 @@
+use super::resample::{downmix, Resampler};
+#[cfg(feature = "runtime")]
+use crate::com::{self, Player, SG_Error, SG_SampleRate, SG_SampleType};
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+    time::Duration,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AudioFormat {
+    Wav,
+    Flac,
+    OggVorbis,
+}
+
+impl AudioFormat {
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "wav" | "wave" => Some(Self::Wav),
+            "flac" => Some(Self::Flac),
+            "ogg" | "oga" => Some(Self::OggVorbis),
+            _ => None,
+        }
+    }
+
+    pub fn from_magic(magic: &[u8; 4]) -> Option<Self> {
+        match magic {
+            b"RIFF" => Some(Self::Wav),
+            b"fLaC" => Some(Self::Flac),
+            b"OggS" => Some(Self::OggVorbis),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    Io(std::io::Error),
+    Wav(hound::Error),
+    Flac(claxon::Error),
+    Vorbis(lewton::VorbisError),
+    UnknownFormat,
+    InvalidStream(&'static str),
+}
+
+impl From<std::io::Error> for DecodeError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<hound::Error> for DecodeError {
+    fn from(error: hound::Error) -> Self {
+        Self::Wav(error)
+    }
+}
+
+impl From<claxon::Error> for DecodeError {
+    fn from(error: claxon::Error) -> Self {
+        Self::Flac(error)
+    }
+}
+
+impl From<lewton::VorbisError> for DecodeError {
+    fn from(error: lewton::VorbisError) -> Self {
+        Self::Vorbis(error)
+    }
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO error: {e}"),
+            Self::Wav(e) => write!(f, "WAV error: {e}"),
+            Self::Flac(e) => write!(f, "FLAC error: {e}"),
+            Self::Vorbis(e) => write!(f, "Vorbis error: {e}"),
+            Self::UnknownFormat => write!(f, "Unknown audio format"),
+            Self::InvalidStream(reason) => write!(f, "Invalid audio stream: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Interleaved audio normalized to [-1, 1], at the source's rate and channel count.
+#[derive(Debug, Clone)]
+pub struct DecodedAudio {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    pub channels: usize,
+}
+
+/// Mono audio resampled to a rate SG_Com accepts.
+#[cfg(feature = "runtime")]
+#[derive(Debug, Clone)]
+pub struct PreparedAudio {
+    pub samples: Vec<f32>,
+    pub sample_rate: SG_SampleRate,
+}
+
+pub fn decode_file(path: impl AsRef<Path>) -> Result<DecodedAudio, DecodeError> {
+    let path = path.as_ref();
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 4];
+    let format = match reader.read_exact(&mut magic) {
+        Ok(()) => AudioFormat::from_magic(&magic),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => None,
+        Err(e) => return Err(e.into()),
+    }
+    .or_else(|| AudioFormat::from_extension(path))
+    .ok_or(DecodeError::UnknownFormat)?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    decode(reader, format)
+}
+
+pub fn decode<R: Read + Seek>(reader: R, format: AudioFormat) -> Result<DecodedAudio, DecodeError> {
+    let audio = match format {
+        AudioFormat::Wav => decode_wav(reader)?,
+        AudioFormat::Flac => decode_flac(reader)?,
+        AudioFormat::OggVorbis => decode_vorbis(reader)?,
+    };
+
+    if audio.channels == 0 {
+        return Err(DecodeError::InvalidStream("No channels"));
+    }
+    if audio.sample_rate == 0 {
+        return Err(DecodeError::InvalidStream("Sample rate is zero"));
+    }
+    Ok(audio)
+}
+
+fn int_scale(bits_per_sample: u32) -> f32 {
+    1.0 / (1u64 << (bits_per_sample - 1)) as f32
+}
+
+fn decode_wav<R: Read>(reader: R) -> Result<DecodedAudio, DecodeError> {
+    let mut reader = hound::WavReader::new(reader)?;
+    let spec = reader.spec();
+
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = int_scale(spec.bits_per_sample as u32);
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 * scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    Ok(DecodedAudio {
+        samples,
+        sample_rate: spec.sample_rate,
+        channels: spec.channels as usize,
+    })
+}
+
+fn decode_flac<R: Read>(reader: R) -> Result<DecodedAudio, DecodeError> {
+    let mut reader = claxon::FlacReader::new(reader)?;
+    let info = reader.streaminfo();
+
+    let scale = int_scale(info.bits_per_sample);
+    let samples = reader
+        .samples()
+        .map(|s| s.map(|s| s as f32 * scale))
+        .collect::<Result<_, _>>()?;
+
+    Ok(DecodedAudio {
+        samples,
+        sample_rate: info.sample_rate,
+        channels: info.channels as usize,
+    })
+}
+
+fn decode_vorbis<R: Read + Seek>(reader: R) -> Result<DecodedAudio, DecodeError> {
+    let mut reader = lewton::inside_ogg::OggStreamReader::new(reader)?;
+
+    let scale = int_scale(16);
+    let mut samples = Vec::new();
+    while let Some(packet) = reader.read_dec_packet_itl()? {
+        samples.extend(packet.into_iter().map(|s| s as f32 * scale));
+    }
+
+    Ok(DecodedAudio {
+        samples,
+        sample_rate: reader.ident_hdr.audio_sample_rate,
+        channels: reader.ident_hdr.audio_channels as usize,
+    })
+}
+
+impl DecodedAudio {
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frame_count() as f64 / self.sample_rate as f64)
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    pub fn to_mono(&self) -> Vec<f32> {
+        downmix(&self.samples, self.channels)
+    }
+
+    pub fn resample(&self, sample_rate: u32) -> DecodedAudio {
+        let mut samples = Vec::new();
+        let mut resampler = Resampler::new(self.sample_rate, sample_rate, self.channels);
+        resampler.process(&self.samples, &mut samples);
+        resampler.finish(&mut samples);
+
+        DecodedAudio {
+            samples,
+            sample_rate,
+            channels: self.channels,
+        }
+    }
+
+    /// Downmixes to mono and resamples to the closest rate SG_Com supports,
+    /// never going below the source rate unless it's above 48kHz. The end is
+    /// padded with silence to a whole 10ms, so every chunk `feed` passes is full.
+    #[cfg(feature = "runtime")]
+    pub fn prepare(&self) -> PreparedAudio {
+        let sample_rate = supported_rate(self.sample_rate);
+
+        let mono = DecodedAudio {
+            samples: self.to_mono(),
+            sample_rate: self.sample_rate,
+            channels: 1,
+        };
+        let mono = if sample_rate.to_rate() as u32 == self.sample_rate {
+            mono
+        } else {
+            mono.resample(sample_rate.to_rate() as u32)
+        };
+
+        let mut audio = PreparedAudio {
+            samples: mono.samples,
+            sample_rate,
+        };
+        let chunk = audio.samples_per(Duration::from_millis(10));
+        let padded = audio.samples.len().div_ceil(chunk) * chunk;
+        audio.samples.resize(padded, 0.0);
+        audio
+    }
+}
+
+#[cfg(feature = "runtime")]
+fn supported_rate(rate: u32) -> SG_SampleRate {
+    [
+        SG_SampleRate::SG_RATE_8KHZ,
+        SG_SampleRate::SG_RATE_12KHZ,
+        SG_SampleRate::SG_RATE_16KHZ,
+        SG_SampleRate::SG_RATE_24KHZ,
+        SG_SampleRate::SG_RATE_32KHZ,
+    ]
+    .into_iter()
+    .find(|supported| supported.to_rate() as u32 >= rate)
+    .unwrap_or(SG_SampleRate::SG_RATE_48KHZ)
+}
+
+#[cfg(feature = "runtime")]
+impl PreparedAudio {
+    pub fn sample_type(&self) -> SG_SampleType {
+        SG_SampleType::SG_SAMPLE_FLOAT32
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.samples.len() as f64 / self.sample_rate.to_rate() as f64)
+    }
+
+    pub fn samples_per(&self, duration: Duration) -> usize {
+        ((self.sample_rate.to_rate() as f64 * duration.as_secs_f64()).round() as usize).max(1)
+    }
+
+    pub fn chunks(&self, duration: Duration) -> std::slice::Chunks<'_, f32> {
+        self.samples.chunks(self.samples_per(duration))
+    }
+
+    pub fn add_player(&self, ctx: &com::SGContext) -> com::Result<Player> {
+        ctx.add_player(self.sample_type(), self.sample_rate)
+    }
+
+    // The player only flushes 10ms at a time, so the input is fed in matching chunks
+    pub fn feed(&self, player: &Player) -> com::Result<()> {
+        if player.sample_rate() != self.sample_rate {
+            return Err(SG_Error::SG_ERROR_INVALID_INPUT_TRAITS.into());
+        }
+        for chunk in self.chunks(Duration::from_millis(10)) {
+            player.add_input_float32(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn wav(
+        spec: hound::WavSpec,
+        write: impl FnOnce(&mut hound::WavWriter<&mut Cursor<Vec<u8>>>),
+    ) -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut bytes, spec).unwrap();
+        write(&mut writer);
+        writer.finalize().unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn decodes_int_wav_to_normalized_floats() {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 22050,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let bytes = wav(spec, |writer| {
+            for sample in [0i16, i16::MIN, 16384, -16384] {
+                writer.write_sample(sample).unwrap();
+            }
+        });
+
+        let magic = bytes[..4].try_into().unwrap();
+        assert_eq!(AudioFormat::from_magic(magic), Some(AudioFormat::Wav));
+        let audio = decode(Cursor::new(bytes), AudioFormat::Wav).unwrap();
+        assert_eq!(audio.samples, [0.0, -1.0, 0.5, -0.5]);
+        assert_eq!((audio.sample_rate, audio.channels), (22050, 2));
+        assert_eq!(audio.frame_count(), 2);
+        assert_eq!(audio.to_mono(), [-0.5, 0.0]);
+    }
+
+    #[test]
+    fn decodes_float_wav() {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let bytes = wav(spec, |writer| {
+            (0..8000).for_each(|i| writer.write_sample(i as f32 / 8000.0).unwrap())
+        });
+
+        let audio = decode(Cursor::new(bytes), AudioFormat::Wav).unwrap();
+        assert_eq!(audio.duration(), Duration::from_secs(1));
+        assert_eq!(audio.samples[4000], 0.5);
+        assert_eq!(audio.resample(16000).frame_count(), 16000);
+    }
+
+    #[test]
+    fn detects_formats() {
+        assert_eq!(AudioFormat::from_magic(b"fLaC"), Some(AudioFormat::Flac));
+        assert_eq!(
+            AudioFormat::from_magic(b"OggS"),
+            Some(AudioFormat::OggVorbis)
+        );
+        assert_eq!(AudioFormat::from_magic(b"ID3\x04"), None);
+        assert_eq!(
+            AudioFormat::from_extension(Path::new("voice.WAV")),
+            Some(AudioFormat::Wav)
+        );
+        assert_eq!(AudioFormat::from_extension(Path::new("voice.mp3")), None);
+        assert!(decode(Cursor::new(b"RIFF....".to_vec()), AudioFormat::Wav).is_err());
+    }
+}
@@ -0,0 +1,328 @@
+use cpal::traits::{DeviceTrait, HostTrait};
+use std::{
+    fmt,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+/// Picks a device by name, by its index in `list`, or the host's default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DeviceSelector {
+    #[default]
+    Default,
+    Index(usize),
+    Name(String),
+}
+
+impl DeviceSelector {
+    /// `default`, an index or a device name.
+    pub fn parse(value: &str) -> Self {
+        if value.eq_ignore_ascii_case("default") {
+            DeviceSelector::Default
+        } else if let Ok(index) = value.parse() {
+            DeviceSelector::Index(index)
+        } else {
+            DeviceSelector::Name(value.to_string())
+        }
+    }
+
+    pub fn from_option(value: Option<&str>) -> Self {
+        value.map(Self::parse).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceSelector::Default => write!(f, "default"),
+            DeviceSelector::Index(index) => write!(f, "#{index}"),
+            DeviceSelector::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+/// The host and devices live input runs on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioDevices {
+    /// cpal host name such as `WASAPI`, `ALSA` or `JACK`; the platform default when unset
+    pub host: Option<String>,
+    pub input: DeviceSelector,
+    pub output: DeviceSelector,
+}
+
+impl AudioDevices {
+    pub fn host(&self) -> cpal::Host {
+        host(self.host.as_deref())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub host: String,
+    pub index: usize,
+    pub name: String,
+    pub is_default: bool,
+    pub configs: Vec<ConfigRange>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigRange {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    pub sample_format: cpal::SampleFormat,
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let default = if self.is_default { " (default)" } else { "" };
+        writeln!(f, "  {}: {}{default}", self.index, self.name)?;
+        for config in &self.configs {
+            writeln!(
+                f,
+                "       {} ch, {}-{} Hz, {}",
+                config.channels,
+                config.min_sample_rate,
+                config.max_sample_rate,
+                config.sample_format
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The named host, falling back to the platform default if it isn't available.
+pub fn host(name: Option<&str>) -> cpal::Host {
+    let Some(name) = name else {
+        return cpal::default_host();
+    };
+    cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name))
+        .and_then(|id| cpal::host_from_id(id).ok())
+        .unwrap_or_else(|| {
+            bevy::log::warn!("Audio host {name} isn't available, using the default");
+            cpal::default_host()
+        })
+}
+
+fn devices(host: &cpal::Host, direction: Direction) -> Vec<cpal::Device> {
+    let devices = match direction {
+        Direction::Input => host.input_devices().map(|devices| devices.collect()),
+        Direction::Output => host.output_devices().map(|devices| devices.collect()),
+    };
+    devices.unwrap_or_default()
+}
+
+pub fn default_device(host: &cpal::Host, direction: Direction) -> Option<cpal::Device> {
+    match direction {
+        Direction::Input => host.default_input_device(),
+        Direction::Output => host.default_output_device(),
+    }
+}
+
+pub fn default_name(host: &cpal::Host, direction: Direction) -> Option<String> {
+    default_device(host, direction)?.name().ok()
+}
+
+pub fn list(host: &cpal::Host, direction: Direction) -> Vec<DeviceInfo> {
+    let default = default_name(host, direction);
+    devices(host, direction)
+        .iter()
+        .enumerate()
+        .map(|(index, device)| {
+            let name = device.name().unwrap_or_else(|_| "<unknown>".to_string());
+            let configs = match direction {
+                Direction::Input => device
+                    .supported_input_configs()
+                    .map(|configs| configs.collect::<Vec<_>>()),
+                Direction::Output => device
+                    .supported_output_configs()
+                    .map(|configs| configs.collect::<Vec<_>>()),
+            };
+            DeviceInfo {
+                host: host.id().name().to_string(),
+                index,
+                is_default: default.as_ref() == Some(&name),
+                name,
+                configs: configs
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|config| ConfigRange {
+                        channels: config.channels(),
+                        min_sample_rate: config.min_sample_rate().0,
+                        max_sample_rate: config.max_sample_rate().0,
+                        sample_format: config.sample_format(),
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// Input and output devices of every available host.
+pub fn list_all() -> Vec<(String, Vec<DeviceInfo>, Vec<DeviceInfo>)> {
+    cpal::available_hosts()
+        .into_iter()
+        .filter_map(|id| cpal::host_from_id(id).ok())
+        .map(|host| {
+            (
+                host.id().name().to_string(),
+                list(&host, Direction::Input),
+                list(&host, Direction::Output),
+            )
+        })
+        .collect()
+}
+
+/// The selected device, or `None` if it isn't connected.
+pub fn find(
+    host: &cpal::Host,
+    direction: Direction,
+    selector: &DeviceSelector,
+) -> Option<cpal::Device> {
+    match selector {
+        DeviceSelector::Default => default_device(host, direction),
+        DeviceSelector::Index(index) => devices(host, direction).into_iter().nth(*index),
+        DeviceSelector::Name(name) => devices(host, direction)
+            .into_iter()
+            .find(|device| device.name().is_ok_and(|device_name| device_name == *name)),
+    }
+}
+
+/// What a `watch` thread last saw of the devices `AudioDevices` selects.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceSnapshot {
+    pub default_input: Option<String>,
+    pub default_output: Option<String>,
+    /// Whether the selected input is connected; always true for the default
+    pub input_found: bool,
+    pub output_found: bool,
+}
+
+impl DeviceSnapshot {
+    pub fn take(host: &cpal::Host, devices: &AudioDevices) -> Self {
+        let found = |direction, selector: &DeviceSelector| {
+            *selector == DeviceSelector::Default || find(host, direction, selector).is_some()
+        };
+        Self {
+            default_input: default_name(host, Direction::Input),
+            default_output: default_name(host, Direction::Output),
+            input_found: found(Direction::Input, &devices.input),
+            output_found: found(Direction::Output, &devices.output),
+        }
+    }
+
+    /// Whether the device to open for `selector` is no longer `name`, the one
+    /// in use (`None` if none could be opened). `fallback` is whether the
+    /// default stood in for a missing selected device.
+    pub fn changed(
+        &self,
+        direction: Direction,
+        selector: &DeviceSelector,
+        name: Option<&String>,
+        fallback: bool,
+    ) -> bool {
+        let (default, found) = match direction {
+            Direction::Input => (&self.default_input, self.input_found),
+            Direction::Output => (&self.default_output, self.output_found),
+        };
+        match selector {
+            DeviceSelector::Default => default.as_ref() != name,
+            // The selected device came back after the default, or nothing, stood in for it
+            _ => (fallback || name.is_none()) && found,
+        }
+    }
+}
+
+/// Polls the devices every `interval` on a background thread, so enumerating
+/// them never stalls the caller. Stops once the receiver is dropped.
+pub fn watch(devices: AudioDevices, interval: Duration) -> Receiver<DeviceSnapshot> {
+    let (sender, receiver) = mpsc::channel();
+    let spawned = thread::Builder::new()
+        .name("audio-device-watch".to_string())
+        .spawn(move || {
+            let host = devices.host();
+            loop {
+                thread::sleep(interval);
+                if sender.send(DeviceSnapshot::take(&host, &devices)).is_err() {
+                    return;
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        bevy::log::error!("Failed to start watching audio devices: {e}");
+    }
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_selectors() {
+        assert_eq!(DeviceSelector::parse("default"), DeviceSelector::Default);
+        assert_eq!(DeviceSelector::parse("Default"), DeviceSelector::Default);
+        assert_eq!(DeviceSelector::parse("2"), DeviceSelector::Index(2));
+        assert_eq!(
+            DeviceSelector::parse("USB Mic"),
+            DeviceSelector::Name("USB Mic".to_string())
+        );
+        assert_eq!(DeviceSelector::from_option(None), DeviceSelector::Default);
+    }
+
+    fn snapshot(default_output: Option<&str>, output_found: bool) -> DeviceSnapshot {
+        DeviceSnapshot {
+            default_output: default_output.map(String::from),
+            output_found,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn follows_the_default_device() {
+        let speakers = "Speakers".to_string();
+        let selector = DeviceSelector::Default;
+        let changed = |snapshot: DeviceSnapshot, name| {
+            snapshot.changed(Direction::Output, &selector, name, false)
+        };
+
+        assert!(!changed(snapshot(Some("Speakers"), true), Some(&speakers)));
+        assert!(changed(snapshot(Some("Headphones"), true), Some(&speakers)));
+        assert!(changed(snapshot(None, true), Some(&speakers)));
+        assert!(changed(snapshot(Some("Speakers"), true), None));
+    }
+
+    #[test]
+    fn retries_a_missing_selected_device() {
+        let speakers = "Speakers".to_string();
+        let selector = DeviceSelector::Name("Headphones".to_string());
+        let changed = |snapshot: DeviceSnapshot, name, fallback| {
+            snapshot.changed(Direction::Output, &selector, name, fallback)
+        };
+
+        // In use, or still missing
+        assert!(!changed(snapshot(None, true), Some(&speakers), false));
+        assert!(!changed(
+            snapshot(Some("Speakers"), false),
+            Some(&speakers),
+            true
+        ));
+        assert!(!changed(snapshot(None, false), None, false));
+        // Back after the default stood in for it, or after nothing could
+        assert!(changed(
+            snapshot(Some("Speakers"), true),
+            Some(&speakers),
+            true
+        ));
+        assert!(changed(snapshot(None, true), None, false));
+    }
+}
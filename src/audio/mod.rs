@@ -0,0 +1,5 @@
+pub mod decode;
+pub mod devices;
+pub mod monitor;
+pub mod output;
+pub mod resample;
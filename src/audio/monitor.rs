@@ -0,0 +1,287 @@
+use super::{output::OutputSource, resample::Resampler};
+use cpal::{FromSample, Sample};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// The longest delay the monitor can add.
+pub const MAX_MONITOR_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MonitorSettings {
+    pub muted: bool,
+    /// Holds the monitored voice back to line it up with the avatar
+    pub delay_ms: u32,
+}
+
+/// Monitor settings shared with the audio callbacks, adjustable while they run.
+#[derive(Debug, Clone, Default)]
+pub struct MonitorControl {
+    muted: Arc<AtomicBool>,
+    delay_ms: Arc<AtomicU32>,
+}
+
+impl MonitorControl {
+    pub fn apply(&self, settings: &MonitorSettings) {
+        self.set_muted(settings.muted);
+        self.set_delay(Duration::from_millis(settings.delay_ms as u64));
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    pub fn toggle_muted(&self) -> bool {
+        !self.muted.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    pub fn delay(&self) -> Duration {
+        Duration::from_millis(self.delay_ms.load(Ordering::Relaxed) as u64)
+    }
+
+    /// Clamped to `MAX_MONITOR_DELAY`.
+    pub fn set_delay(&self, delay: Duration) {
+        let delay = delay.min(MAX_MONITOR_DELAY).as_millis() as u32;
+        self.delay_ms.store(delay, Ordering::Relaxed);
+    }
+}
+
+// Interleaved samples at the output's rate and channel count, oldest first
+type MonitorBuffer = Arc<Mutex<VecDeque<f32>>>;
+
+/// Connects an input callback to an output callback through a FIFO buffer,
+/// converting from the input's rate to the output's. The voice is downmixed
+/// to mono and played on the first two output channels.
+pub fn monitor(
+    input_rate: u32,
+    input_channels: usize,
+    output_rate: u32,
+    output_channels: usize,
+    control: MonitorControl,
+) -> (MonitorInput, MonitorOutput) {
+    // Room for the longest delay plus a little jitter, allocated once
+    let capacity =
+        ((MAX_MONITOR_DELAY.as_secs_f64() + 0.5) * output_rate as f64) as usize * output_channels;
+    let buffer: MonitorBuffer = Arc::new(Mutex::new(VecDeque::with_capacity(capacity)));
+
+    let input = MonitorInput {
+        buffer: buffer.clone(),
+        capacity,
+        input_channels: input_channels.max(1),
+        output_channels,
+        resampler: Resampler::new(input_rate, output_rate, 1),
+        mono: Vec::new(),
+        resampled: Vec::new(),
+    };
+    let output = MonitorOutput {
+        buffer,
+        rate: output_rate,
+        channels: output_channels,
+        control,
+        primed: false,
+    };
+    (input, output)
+}
+
+pub struct MonitorInput {
+    buffer: MonitorBuffer,
+    capacity: usize,
+    input_channels: usize,
+    output_channels: usize,
+    resampler: Resampler,
+    // Reused between callbacks
+    mono: Vec<f32>,
+    resampled: Vec<f32>,
+}
+
+impl MonitorInput {
+    pub fn push<T>(&mut self, data: &[T])
+    where
+        T: Sample,
+        f32: FromSample<T>,
+    {
+        self.mono.clear();
+        self.mono
+            .extend(data.chunks_exact(self.input_channels).map(|frame| {
+                frame
+                    .iter()
+                    .map(|sample| sample.to_sample::<f32>())
+                    .sum::<f32>()
+                    / self.input_channels as f32
+            }));
+        self.resampled.clear();
+        self.resampler.process(&self.mono, &mut self.resampled);
+
+        let mut buffer = self.buffer.lock().unwrap();
+        for sample in &self.resampled {
+            buffer.extend((0..self.output_channels).map(
+                |channel| {
+                    if channel < 2 {
+                        *sample
+                    } else {
+                        0.0
+                    }
+                },
+            ));
+        }
+
+        // The output stopped pulling; drop the oldest frames rather than grow
+        let excess = buffer.len().saturating_sub(self.capacity);
+        let excess =
+            (excess.div_ceil(self.output_channels) * self.output_channels).min(buffer.len());
+        buffer.drain(..excess);
+    }
+}
+
+pub struct MonitorOutput {
+    buffer: MonitorBuffer,
+    rate: u32,
+    channels: usize,
+    control: MonitorControl,
+    // Whether enough is buffered to cover the delay
+    primed: bool,
+}
+
+impl MonitorOutput {
+    pub fn fill<T>(&mut self, data: &mut [T])
+    where
+        T: Sample + FromSample<f32>,
+    {
+        let delay =
+            (self.control.delay().as_secs_f64() * self.rate as f64) as usize * self.channels;
+        // Input and output callbacks don't line up, so allow some jitter
+        let slack = data.len() * 2;
+        let mut buffer = self.buffer.lock().unwrap();
+
+        if self.primed && buffer.len() + slack < delay {
+            // The delay grew; play silence until it's covered
+            self.primed = false;
+        }
+        if buffer.len() > delay + data.len() + slack {
+            // Drifted behind or the delay shrank; skip ahead to keep latency down
+            let excess = buffer.len() - delay - data.len();
+            buffer.drain(..excess - excess % self.channels);
+        }
+        if !self.primed {
+            if buffer.len() < delay + data.len() {
+                data.fill(T::EQUILIBRIUM);
+                return;
+            }
+            self.primed = true;
+        }
+
+        let available = buffer.len().min(data.len());
+        let muted = self.control.is_muted();
+        for (out, sample) in data.iter_mut().zip(buffer.drain(..available)) {
+            *out = if muted {
+                T::EQUILIBRIUM
+            } else {
+                T::from_sample(sample)
+            };
+        }
+        if available < data.len() {
+            // Underrun; build the delay back up before playing again
+            data[available..].fill(T::EQUILIBRIUM);
+            self.primed = false;
+        }
+    }
+}
+
+impl OutputSource for MonitorOutput {
+    fn fill<T>(&mut self, data: &mut [T])
+    where
+        T: Sample + FromSample<f32>,
+    {
+        MonitorOutput::fill(self, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp(len: usize) -> Vec<f32> {
+        (1..=len).map(|i| i as f32 / 100.0).collect()
+    }
+
+    #[test]
+    fn downmixes_onto_the_first_two_channels() {
+        let (mut input, mut output) = monitor(8000, 2, 8000, 3, MonitorControl::default());
+        input.push(&[0.1f32, 0.3, 0.2, 0.2]);
+
+        let mut data = [1.0f32; 6];
+        output.fill(&mut data);
+        assert_eq!(data, [0.2, 0.2, 0.0, 0.2, 0.2, 0.0]);
+    }
+
+    #[test]
+    fn holds_output_back_by_the_delay() {
+        let control = MonitorControl::default();
+        control.set_delay(Duration::from_millis(1));
+        let (mut input, mut output) = monitor(8000, 1, 8000, 1, control);
+        let samples = ramp(20);
+
+        // 8 samples of delay plus a buffer's worth before anything plays
+        input.push(&samples[..10]);
+        let mut data = [1.0f32; 4];
+        output.fill(&mut data);
+        assert_eq!(data, [0.0; 4]);
+
+        input.push(&samples[10..]);
+        output.fill(&mut data);
+        assert_eq!(data, samples[..4]);
+        output.fill(&mut data);
+        assert_eq!(data, samples[4..8]);
+    }
+
+    #[test]
+    fn underruns_with_silence() {
+        let (mut input, mut output) = monitor(8000, 1, 8000, 1, MonitorControl::default());
+        input.push(&ramp(6));
+
+        let mut data = [1.0f32; 4];
+        output.fill(&mut data);
+        assert_eq!(data, ramp(4)[..]);
+        output.fill(&mut data);
+        assert_eq!(data, [0.05, 0.06, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn mutes_without_falling_behind() {
+        let control = MonitorControl::default();
+        let (mut input, mut output) = monitor(8000, 1, 8000, 1, control.clone());
+        input.push(&ramp(8));
+
+        control.set_muted(true);
+        let mut data = [1.0f32; 4];
+        output.fill(&mut data);
+        assert_eq!(data, [0.0; 4]);
+
+        control.set_muted(false);
+        output.fill(&mut data);
+        assert_eq!(data, ramp(8)[4..]);
+    }
+
+    #[test]
+    fn converts_to_the_output_rate_and_format() {
+        let (mut input, mut output) = monitor(16000, 1, 8000, 1, MonitorControl::default());
+        input.push(&[0.5f32; 32]);
+
+        let mut data = [0i16; 8];
+        output.fill(&mut data);
+        assert!(data
+            .iter()
+            .all(|sample| *sample == 0.5f32.to_sample::<i16>()));
+    }
+}
@@ -0,0 +1,46 @@
+use cpal::{traits::DeviceTrait, FromSample, Sample, SampleFormat};
+
+/// Fills output buffers of any sample format from `f32` audio.
+pub trait OutputSource: Send + 'static {
+    fn fill<T>(&mut self, data: &mut [T])
+    where
+        T: Sample + FromSample<f32>;
+}
+
+/// Opens an output stream in the device's sample format, pulling from `source`.
+pub fn build_output_stream(
+    device: &cpal::Device,
+    config: &cpal::SupportedStreamConfig,
+    mut source: impl OutputSource,
+    err_fn: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<cpal::Stream, Box<dyn std::error::Error>> {
+    let stream_config = config.config();
+    let stream = match config.sample_format() {
+        SampleFormat::I16 => device.build_output_stream(
+            &stream_config,
+            move |data: &mut [i16], _| source.fill(data),
+            err_fn,
+            None,
+        ),
+        SampleFormat::I32 => device.build_output_stream(
+            &stream_config,
+            move |data: &mut [i32], _| source.fill(data),
+            err_fn,
+            None,
+        ),
+        SampleFormat::U16 => device.build_output_stream(
+            &stream_config,
+            move |data: &mut [u16], _| source.fill(data),
+            err_fn,
+            None,
+        ),
+        SampleFormat::F32 => device.build_output_stream(
+            &stream_config,
+            move |data: &mut [f32], _| source.fill(data),
+            err_fn,
+            None,
+        ),
+        format => return Err(format!("Unsupported output sample format {format}").into()),
+    }?;
+    Ok(stream)
+}
@@ -0,0 +1,160 @@
+/// Streaming linear-interpolation resampler for interleaved f32 audio.
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    channels: usize,
+    // Input frames consumed per output frame
+    step: f64,
+    // Fractional read position into `pending`, in frames
+    position: f64,
+    pending: Vec<f32>,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32, channels: usize) -> Self {
+        assert!(
+            from_rate > 0 && to_rate > 0,
+            "Sample rates must be non-zero"
+        );
+        assert!(channels > 0, "Channel count must be non-zero");
+
+        Self {
+            channels,
+            step: from_rate as f64 / to_rate as f64,
+            position: 0.0,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.step == 1.0
+    }
+
+    fn pending_frames(&self) -> usize {
+        self.pending.len() / self.channels
+    }
+
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        if self.is_passthrough() && self.pending.is_empty() {
+            output.extend_from_slice(input);
+            return;
+        }
+
+        self.pending.extend_from_slice(input);
+
+        let frames = self.pending_frames();
+        while (self.position as usize) + 1 < frames {
+            let index = self.position as usize;
+            let t = (self.position - index as f64) as f32;
+            let a = &self.pending[index * self.channels..(index + 1) * self.channels];
+            let b = &self.pending[(index + 1) * self.channels..(index + 2) * self.channels];
+            output.extend(a.iter().zip(b).map(|(a, b)| a + (b - a) * t));
+            self.position += self.step;
+        }
+
+        // Keep the frame we're interpolating from, drop everything before it
+        let consumed = (self.position as usize).min(frames.saturating_sub(1));
+        self.pending.drain(..consumed * self.channels);
+        self.position -= consumed as f64;
+    }
+
+    // Emits whatever is left by holding the last frame, then resets
+    pub fn finish(&mut self, output: &mut Vec<f32>) {
+        let frames = self.pending_frames();
+        if frames > 0 {
+            let last = &self.pending[(frames - 1) * self.channels..];
+            while (self.position as usize) < frames {
+                output.extend_from_slice(last);
+                self.position += self.step;
+            }
+        }
+        self.pending.clear();
+        self.position = 0.0;
+    }
+}
+
+/// Averages interleaved channels down to mono.
+pub fn downmix(samples: &[f32], channels: usize) -> Vec<f32> {
+    if channels <= 1 {
+        return samples.to_vec();
+    }
+
+    samples
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect()
+}
+
+/// Maps interleaved audio between channel counts. Mono is copied to every
+/// output channel and anything going to mono is averaged; otherwise channels
+/// are matched up in order and extra output channels are left silent.
+pub fn remix(samples: &[f32], from_channels: usize, to_channels: usize) -> Vec<f32> {
+    if from_channels == to_channels {
+        return samples.to_vec();
+    }
+    if to_channels == 1 {
+        return downmix(samples, from_channels);
+    }
+
+    let mut output = Vec::with_capacity(samples.len() / from_channels * to_channels);
+    for frame in samples.chunks_exact(from_channels) {
+        if from_channels == 1 {
+            output.extend(std::iter::repeat_n(frame[0], to_channels));
+        } else {
+            output
+                .extend((0..to_channels).map(|channel| frame.get(channel).copied().unwrap_or(0.0)));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_matching_rates_through() {
+        let mut resampler = Resampler::new(48000, 48000, 2);
+        assert!(resampler.is_passthrough());
+
+        let mut output = Vec::new();
+        resampler.process(&[0.1, 0.2, 0.3, 0.4], &mut output);
+        assert_eq!(output, [0.1, 0.2, 0.3, 0.4]);
+    }
+
+    #[test]
+    fn interpolates_when_upsampling() {
+        let mut resampler = Resampler::new(8000, 16000, 1);
+        let mut output = Vec::new();
+        resampler.process(&[0.0, 1.0], &mut output);
+        resampler.process(&[0.0], &mut output);
+        resampler.finish(&mut output);
+        assert_eq!(output, [0.0, 0.5, 1.0, 0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn keeps_channels_apart_across_buffers() {
+        let mut resampler = Resampler::new(48000, 16000, 2);
+        let input: Vec<f32> = (0..300).flat_map(|_| [1.0, -1.0]).collect();
+
+        let mut output = Vec::new();
+        for chunk in input.chunks(50) {
+            resampler.process(chunk, &mut output);
+        }
+        resampler.finish(&mut output);
+        assert_eq!(output.len(), 200);
+        assert!(output.chunks(2).all(|frame| frame == [1.0, -1.0]));
+    }
+
+    #[test]
+    fn remixes_between_channel_counts() {
+        assert_eq!(remix(&[0.5, 0.25], 1, 2), [0.5, 0.5, 0.25, 0.25]);
+        assert_eq!(remix(&[1.0, 0.0, 0.5, 0.5], 2, 1), [0.5, 0.5]);
+        assert_eq!(remix(&[0.1, 0.2, 0.3], 3, 2), [0.1, 0.2]);
+        assert_eq!(remix(&[0.1, 0.2], 2, 4), [0.1, 0.2, 0.0, 0.0]);
+        assert_eq!(downmix(&[0.2, 0.4], 1), [0.2, 0.4]);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{
+    audio::decode::{self, DecodeError, PreparedAudio},
+    com::{self, Player, SGContext},
+    track::{Rig, Track},
+};
+use std::{path::Path, time::Duration};
+
+#[derive(Debug)]
+pub enum BakeError {
+    Decode(DecodeError),
+    Com(com::Error),
+    InvalidFrameRate(f32),
+}
+
+impl From<DecodeError> for BakeError {
+    fn from(error: DecodeError) -> Self {
+        Self::Decode(error)
+    }
+}
+
+impl From<com::Error> for BakeError {
+    fn from(error: com::Error) -> Self {
+        Self::Com(error)
+    }
+}
+
+impl std::fmt::Display for BakeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "{e}"),
+            Self::Com(e) => write!(f, "{e}"),
+            Self::InvalidFrameRate(frame_rate) => write!(f, "Invalid frame rate {frame_rate}"),
+        }
+    }
+}
+
+impl std::error::Error for BakeError {}
+
+/// Runs a whole clip through `player` at a fixed frame rate. The player must
+/// have been created from `audio`'s sample type and rate. Each frame is the
+/// pose at the end of its step, so the first is stamped at `1 / frame_rate`.
+pub fn bake(player: &Player, audio: &PreparedAudio, frame_rate: f32) -> Result<Track, BakeError> {
+    if !(frame_rate.is_finite() && frame_rate > 0.0) {
+        return Err(BakeError::InvalidFrameRate(frame_rate));
+    }
+
+    let mut track = Track::new(Rig::from_player(player), frame_rate);
+    track.sg_version = Some(SGContext::version());
+
+    let frame_delta = Duration::from_secs_f32(1.0 / frame_rate);
+    let frame_count = (audio.duration().as_secs_f32() * frame_rate).ceil() as usize;
+    let samples_per_10ms = audio.samples_per(Duration::from_millis(10));
+
+    let mut fed = 0;
+    for i in 0..frame_count {
+        // Keep the input a frame ahead of the output
+        let needed = audio
+            .samples_per(frame_delta * (i as u32 + 1))
+            .min(audio.samples.len());
+        // `prepare` pads the audio, so these are whole 10ms chunks
+        while fed < needed {
+            let end = (fed + samples_per_10ms).min(audio.samples.len());
+            player.add_input_float32(&audio.samples[fed..end])?;
+            fed = end;
+        }
+
+        // Processing advances the player to the end of this frame
+        let values = player.process(frame_delta)?;
+        track.push((i + 1) as f32 / frame_rate, values);
+    }
+
+    Ok(track)
+}
+
+pub fn bake_file(
+    ctx: &SGContext,
+    path: impl AsRef<Path>,
+    frame_rate: f32,
+) -> Result<Track, BakeError> {
+    let audio = decode::decode_file(path)?.prepare();
+    let player = audio.add_player(ctx)?;
+    bake(&player, &audio, frame_rate)
+}
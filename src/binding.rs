@@ -0,0 +1,411 @@
+use crate::{
+    clip::{TrackAnimation, TrackClip},
+    facial_anim::{FacialAnim, FacialAnimator},
+    retarget::{BoneBinding, MorphBinding},
+    vrm::VrmModel,
+};
+use bevy::{log, prelude::*};
+
+/// Binds every `MorphWeights` in the world to the animator that owns it: the
+/// nearest `FacialAnimator` among its ancestors, or the app-wide `FacialAnim`.
+/// Each mesh resolves the retarget map against its own morph target names, and
+/// is rebound when it's spawned, its mesh is reloaded or the retarget changes.
+/// Entities playing a `TrackAnimation` or a built `TrackClip` are left to their
+/// `AnimationPlayer`.
+///
+/// Joints named by the map's bone mappings are bound the same way and posed
+/// after Bevy's animation systems, so they override any clip animating them.
+pub struct MorphBindingPlugin;
+
+impl Plugin for MorphBindingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (bind_morph_targets, apply_morph_targets).chain());
+        app.add_systems(Update, bind_bones);
+        app.add_systems(
+            PostUpdate,
+            apply_bones
+                .after(bevy::app::Animation)
+                .before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct BoundMorphs {
+    /// `None` for the app-wide animator
+    pub animator: Option<Entity>,
+    mesh: AssetId<Mesh>,
+    retarget_generation: u32,
+    binding: MorphBinding,
+}
+
+impl BoundMorphs {
+    pub fn binding(&self) -> &MorphBinding {
+        &self.binding
+    }
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct BoundBones {
+    /// `None` for the app-wide animator
+    pub animator: Option<Entity>,
+    retarget_generation: u32,
+    binding: BoneBinding,
+}
+
+impl BoundBones {
+    pub fn binding(&self) -> &BoneBinding {
+        &self.binding
+    }
+}
+
+// The nearest self-or-ancestor `FacialAnimator`, `None` for the app-wide one
+fn owner_of(
+    entity: Entity,
+    animators: &Query<&FacialAnimator>,
+    parents: &Query<&Parent>,
+) -> Option<Entity> {
+    std::iter::once(entity)
+        .chain(parents.iter_ancestors(entity))
+        .find(|ancestor| animators.contains(*ancestor))
+}
+
+// Entities not driven by an `AnimationPlayer` instead
+type Unclipped = (Without<TrackAnimation>, Without<TrackClip>);
+
+type MorphTargetQuery<'a> = (
+    Entity,
+    &'a MorphWeights,
+    Option<&'a Name>,
+    Option<&'a BoundMorphs>,
+);
+
+fn bind_morph_targets(
+    mut commands: Commands,
+    mut mesh_events: EventReader<AssetEvent<Mesh>>,
+    anim: Res<FacialAnim>,
+    animators: Query<&FacialAnimator>,
+    targets: Query<MorphTargetQuery, Unclipped>,
+    parents: Query<&Parent>,
+    meshes: Res<Assets<Mesh>>,
+) {
+    let modified: Vec<AssetId<Mesh>> = mesh_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+
+    for (entity, weights, name, bound) in &targets {
+        let Some(mesh) = weights.first_mesh() else {
+            continue;
+        };
+
+        let owner = owner_of(entity, &animators, &parents);
+        let animator = match owner {
+            Some(owner) => animators.get(owner).unwrap(),
+            None => &**anim,
+        };
+
+        let up_to_date = bound.is_some_and(|bound| {
+            bound.animator == owner
+                && bound.mesh == mesh.id()
+                && bound.retarget_generation == animator.retarget_generation()
+                && !modified.contains(&bound.mesh)
+        });
+        if up_to_date {
+            continue;
+        }
+
+        // Not loaded yet; try again next frame
+        let Some(morph_names) = meshes.get(mesh).and_then(|mesh| mesh.morph_target_names()) else {
+            continue;
+        };
+
+        let binding = animator.retarget().bind(&animator.rig, morph_names);
+        let name = name.map_or_else(|| entity.to_string(), |name| name.to_string());
+        log::info!(
+            "Bound {} of {} morph targets on {name}",
+            (0..binding.morph_count())
+                .filter(|index| binding.is_bound(*index))
+                .count(),
+            binding.morph_count()
+        );
+
+        commands.entity(entity).insert(BoundMorphs {
+            animator: owner,
+            mesh: mesh.id(),
+            retarget_generation: animator.retarget_generation(),
+            binding,
+        });
+    }
+}
+
+fn apply_morph_targets(
+    anim: Res<FacialAnim>,
+    vrm: Option<Res<VrmModel>>,
+    animators: Query<&FacialAnimator>,
+    mut targets: Query<(&BoundMorphs, &mut MorphWeights), Unclipped>,
+) {
+    for (bound, mut weights) in &mut targets {
+        let processed_data = match bound.animator {
+            Some(animator) => animators
+                .get(animator)
+                .ok()
+                .and_then(|animator| animator.processed_data.as_ref()),
+            // VRM models are driven through their expressions instead
+            None if vrm.is_some() => None,
+            None => anim.processed_data.as_ref(),
+        };
+
+        if let Some(processed_data) = processed_data {
+            bound.binding.apply(processed_data, weights.weights_mut());
+        }
+    }
+}
+
+type JointQuery<'a> = (Entity, &'a Name, &'a mut Transform, Option<&'a BoundBones>);
+
+fn bind_bones(
+    mut commands: Commands,
+    anim: Res<FacialAnim>,
+    animators: Query<&FacialAnimator>,
+    mut joints: Query<JointQuery>,
+    parents: Query<&Parent>,
+) {
+    // Most named entities aren't driven joints, so only look up owners for these
+    let bone_names: Vec<String> = std::iter::once(&**anim)
+        .chain(&animators)
+        .flat_map(FacialAnimator::bone_names)
+        .collect();
+    if bone_names.is_empty() && joints.iter().all(|(.., bound)| bound.is_none()) {
+        return;
+    }
+
+    for (entity, name, mut transform, bound) in &mut joints {
+        if bound.is_none() && !bone_names.iter().any(|bone| bone == name.as_str()) {
+            continue;
+        }
+
+        let owner = owner_of(entity, &animators, &parents);
+        let animator = match owner {
+            Some(owner) => animators.get(owner).unwrap(),
+            None => &**anim,
+        };
+
+        let up_to_date = bound.is_some_and(|bound| {
+            bound.animator == owner && bound.retarget_generation == animator.retarget_generation()
+        });
+        if up_to_date {
+            continue;
+        }
+
+        // Keep the rest pose from the first binding, the transform is posed by now
+        let rest = match bound {
+            Some(bound) => (bound.binding.rest_rotation, bound.binding.rest_translation),
+            None => (transform.rotation, transform.translation),
+        };
+        let binding = animator.retarget().bind_bone(&animator.rig, name, rest);
+
+        if binding.is_empty() {
+            if bound.is_some() {
+                transform.rotation = rest.0;
+                transform.translation = rest.1;
+                commands.entity(entity).remove::<BoundBones>();
+            }
+            continue;
+        }
+
+        log::info!("Bound {} channels on joint {name}", binding.len());
+        commands.entity(entity).insert(BoundBones {
+            animator: owner,
+            retarget_generation: animator.retarget_generation(),
+            binding,
+        });
+    }
+}
+
+fn apply_bones(
+    anim: Res<FacialAnim>,
+    animators: Query<&FacialAnimator>,
+    mut joints: Query<(&BoundBones, &mut Transform)>,
+) {
+    for (bound, mut transform) in &mut joints {
+        let processed_data = match bound.animator {
+            Some(animator) => animators
+                .get(animator)
+                .ok()
+                .and_then(|animator| animator.processed_data.as_ref()),
+            None => anim.processed_data.as_ref(),
+        };
+
+        if let Some(processed_data) = processed_data {
+            let (rotation, translation) = bound.binding.evaluate(processed_data);
+            transform.rotation = rotation;
+            transform.translation = translation;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        facial_anim::tests::animator,
+        retarget::{BoneMapping, MorphMapping, RetargetMap},
+    };
+    use bevy::{asset::RenderAssetUsages, render::mesh::PrimitiveTopology};
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), MorphBindingPlugin))
+            .init_asset::<Mesh>();
+        let mut anim = animator("app", &[1.0, 0.0]);
+        anim.processed_data = Some(vec![vec![1.0, 0.0]]);
+        app.insert_resource(FacialAnim(anim));
+        app
+    }
+
+    fn mesh(app: &mut App, morphs: &[&str]) -> MorphWeights {
+        let names = morphs.iter().map(|morph| morph.to_string()).collect();
+        let mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_morph_target_names(names);
+        let handle = app.world_mut().resource_mut::<Assets<Mesh>>().add(mesh);
+        MorphWeights::new(vec![0.0; morphs.len()], Some(handle)).unwrap()
+    }
+
+    fn weights(app: &App, entity: Entity) -> &[f32] {
+        app.world().get::<MorphWeights>(entity).unwrap().weights()
+    }
+
+    fn transform(app: &App, entity: Entity) -> Transform {
+        *app.world().get::<Transform>(entity).unwrap()
+    }
+
+    #[test]
+    fn binds_every_mesh_to_its_nearest_animator() {
+        let mut app = app();
+        let face = mesh(&mut app, &["mouthSmile_pose", "jawOpen_pose", "browUp"]);
+        let teeth = mesh(&mut app, &["jawOpen_pose"]);
+        let loose = mesh(&mut app, &["jawOpen_pose"]);
+
+        let mut character = animator("character", &[0.25, 0.5]);
+        character.processed_data = Some(vec![vec![0.25, 0.5]]);
+        let world = app.world_mut();
+        let face = world.spawn((face, Name::new("Face"))).id();
+        let teeth = world.spawn((teeth, Name::new("Teeth"))).id();
+        let head = world.spawn(Name::new("Head")).add_child(teeth).id();
+        let character = world.spawn(character).add_children(&[face, head]).id();
+        let loose = world.spawn(loose).id();
+
+        app.update();
+        assert_eq!(weights(&app, face), [0.5, 0.25, 0.0]);
+        assert_eq!(weights(&app, teeth), [0.25]);
+        assert_eq!(weights(&app, loose), [1.0]);
+        let bound = |entity| app.world().get::<BoundMorphs>(entity).unwrap().animator;
+        assert_eq!(bound(face), Some(character));
+        assert_eq!(bound(teeth), Some(character));
+        assert_eq!(bound(loose), None);
+    }
+
+    #[test]
+    fn rebinds_when_the_retarget_or_mesh_changes() {
+        let mut app = app();
+        let face = mesh(&mut app, &["jawOpen_pose", "mouthSmile_pose"]);
+        let teeth = mesh(&mut app, &["jawOpen_pose"]);
+        let teeth_mesh = teeth.first_mesh().unwrap().id();
+        let face = app.world_mut().spawn(face).id();
+        let teeth = app.world_mut().spawn(teeth).id();
+        app.update();
+        assert_eq!(weights(&app, face), [1.0, 0.0]);
+
+        app.world_mut()
+            .resource_mut::<FacialAnim>()
+            .set_retarget(RetargetMap {
+                morphs: vec![MorphMapping::new(
+                    "blendBoard",
+                    "jawOpen",
+                    "mouthSmile_pose",
+                )],
+                ..Default::default()
+            });
+        app.update();
+        // Morphs the new map leaves unbound keep their last weight
+        assert_eq!(weights(&app, face), [1.0, 1.0]);
+        let bound = |app: &App| {
+            let bound = app.world().get::<BoundMorphs>(teeth).unwrap();
+            bound.binding().is_bound(0)
+        };
+        assert!(!bound(&app));
+
+        let reloaded = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_morph_target_names(vec!["mouthSmile_pose".to_string()]);
+        app.world_mut()
+            .resource_mut::<Assets<Mesh>>()
+            .insert(teeth_mesh, reloaded);
+        // The event is only read on the next update
+        app.update();
+        assert!(!bound(&app));
+        app.update();
+        assert!(bound(&app));
+    }
+
+    #[test]
+    fn poses_the_jaw_and_restores_it_when_unmapped() {
+        let mut app = app();
+        let face = mesh(&mut app, &["jawOpen_pose"]);
+        let mut character = animator("jaw", &[0.5, 0.0]);
+        character.processed_data = Some(vec![vec![0.5, 0.0]]);
+        let retarget = RetargetMap {
+            morphs: vec![MorphMapping::new("blendBoard", "jawOpen", "jawOpen_pose")],
+            bones: vec![BoneMapping::new(
+                "blendBoard",
+                "jawOpen",
+                "Jaw",
+                [0.0, 90.0],
+            )],
+            ..Default::default()
+        };
+        let character = character.with_retarget(retarget.clone());
+
+        let rest = Transform::from_xyz(0.0, 1.0, 0.0).with_rotation(Quat::from_rotation_z(0.5));
+        let world = app.world_mut();
+        let face = world.spawn(face).id();
+        let jaw = world.spawn((Name::new("Jaw"), rest)).id();
+        let character = world.spawn(character).add_children(&[face, jaw]).id();
+        // Same name, but the app-wide map drives no joints
+        let other = world.spawn((Name::new("Jaw"), rest)).id();
+
+        app.update();
+        assert_eq!(weights(&app, face), [0.5]);
+        let opened = rest.rotation * Quat::from_rotation_x(45f32.to_radians());
+        assert!(transform(&app, jaw).rotation.abs_diff_eq(opened, 1e-5));
+        assert_eq!(transform(&app, jaw).translation, rest.translation);
+        assert_eq!(transform(&app, other), rest);
+
+        // Rebinding keeps the first rest pose rather than the posed transform
+        let mut animator = app
+            .world_mut()
+            .get_mut::<FacialAnimator>(character)
+            .unwrap();
+        animator.set_retarget(retarget);
+        app.update();
+        assert!(transform(&app, jaw).rotation.abs_diff_eq(opened, 1e-5));
+
+        let mut animator = app
+            .world_mut()
+            .get_mut::<FacialAnimator>(character)
+            .unwrap();
+        animator.set_retarget(RetargetMap::default());
+        app.update();
+        assert_eq!(transform(&app, jaw), rest);
+        assert!(app.world().get::<BoundBones>(jaw).is_none());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::facial_anim::{process_animators, process_data, FacialAnim, FacialAnimator};
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
+    log,
+    prelude::*,
+};
+use std::sync::Arc;
+
+/// Loads `.k` character files as assets and rebuilds the SG player of every
+/// animator referencing one when it's loaded, modified or swapped for another.
+/// `character` is loaded for the app-wide animator in place of the built-in one.
+pub struct CharacterPlugin {
+    pub character: Option<String>,
+}
+
+impl Plugin for CharacterPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Character>()
+            .init_asset_loader::<CharacterLoader>()
+            .add_systems(
+                PreUpdate,
+                swap_characters
+                    .before(process_data)
+                    .before(process_animators),
+            );
+
+        if let Some(path) = self.character.clone() {
+            app.add_systems(
+                Startup,
+                move |asset_server: Res<AssetServer>, mut anim: ResMut<FacialAnim>| {
+                    anim.set_character(asset_server.load(path.clone()));
+                },
+            );
+        }
+    }
+}
+
+/// A voice-analysis character, as read from an SG_Com `.k` file.
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct Character {
+    pub data: Arc<[u8]>,
+}
+
+#[derive(Default)]
+pub struct CharacterLoader;
+
+impl AssetLoader for CharacterLoader {
+    type Asset = Character;
+    type Settings = ();
+    type Error = std::io::Error;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Character, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        if bytes.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Empty character file",
+            ));
+        }
+        Ok(Character { data: bytes.into() })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["k"]
+    }
+}
+
+// The character to rebuild the animator's player with, if it's loaded and out of date
+fn pending_character<'a>(
+    animator: &FacialAnimator,
+    modified: &[AssetId<Character>],
+    characters: &'a Assets<Character>,
+) -> Option<(AssetId<Character>, &'a Character)> {
+    let id = animator.character()?.id();
+    if animator.loaded_character() == Some(id) && !modified.contains(&id) {
+        return None;
+    }
+    characters.get(id).map(|character| (id, character))
+}
+
+fn swap_character(animator: &mut FacialAnimator, id: AssetId<Character>, character: &Character) {
+    match animator.load_character(id, character) {
+        Ok(()) => log::info!(
+            "Loaded character {id} ({} channels)",
+            animator.rig.channel_count()
+        ),
+        Err(e) => log::error!("Failed to load character {id}: {e}"),
+    }
+}
+
+fn swap_characters(
+    mut events: EventReader<AssetEvent<Character>>,
+    characters: Res<Assets<Character>>,
+    mut anim: ResMut<FacialAnim>,
+    mut animators: Query<&mut FacialAnimator>,
+) {
+    let modified: Vec<AssetId<Character>> = events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+
+    if let Some((id, character)) = pending_character(&anim, &modified, &characters) {
+        swap_character(&mut anim, id, character);
+    }
+    for mut animator in &mut animators {
+        if let Some((id, character)) = pending_character(&animator, &modified, &characters) {
+            swap_character(&mut animator, id, character);
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{
+    export,
+    retarget::{MorphBinding, RetargetMap},
+    track::Track,
+};
+use bevy::{
+    animation::{
+        gltf_curves::{WideKeyframeCurveError, WideLinearKeyframeCurve},
+        AnimationTarget, AnimationTargetId,
+    },
+    asset::{io::Reader, AssetLoader, LoadContext},
+    log,
+    prelude::*,
+};
+
+pub struct TrackClipPlugin;
+
+impl Plugin for TrackClipPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Track>()
+            .init_asset_loader::<TrackLoader>()
+            .add_systems(Update, build_track_clips);
+    }
+}
+
+/// Loads tracks written by `export::json`.
+#[derive(Default)]
+pub struct TrackLoader;
+
+impl AssetLoader for TrackLoader {
+    type Asset = Track;
+    type Settings = ();
+    type Error = std::io::Error;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Track, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        export::json::from_slice(&bytes)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["track.json"]
+    }
+}
+
+#[derive(Debug)]
+pub enum TrackClipError {
+    /// The binding drives no morph targets
+    Unbound,
+    Curve(WideKeyframeCurveError),
+}
+
+impl std::fmt::Display for TrackClipError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unbound => write!(f, "No morph targets are bound"),
+            Self::Curve(WideKeyframeCurveError::CoreError(e)) => write!(f, "{e}"),
+            Self::Curve(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for TrackClipError {}
+
+/// Converts a baked track into a clip animating the morph weights of `target`.
+/// Fails if nothing is bound or the track is too short to interpolate.
+pub fn track_to_clip(
+    track: &Track,
+    binding: &MorphBinding,
+    target: AnimationTargetId,
+) -> Result<AnimationClip, TrackClipError> {
+    if binding.morph_count() == 0 {
+        return Err(TrackClipError::Unbound);
+    }
+
+    let times = track.frames.iter().map(|frame| frame.time);
+    let weights = track
+        .frames
+        .iter()
+        .flat_map(|frame| binding.evaluate(&frame.values));
+    let curve = WideLinearKeyframeCurve::new(times, weights).map_err(TrackClipError::Curve)?;
+
+    let mut clip = AnimationClip::default();
+    clip.add_curve_to_target(target, WeightsCurve(curve));
+    Ok(clip)
+}
+
+/// Plays a baked track on the entity's `MorphWeights` through an `AnimationPlayer`.
+#[derive(Component, Clone)]
+pub struct TrackAnimation {
+    pub track: Handle<Track>,
+    /// Defaults to `RetargetMap::pose_suffix` for the track's rig
+    pub retarget: Option<RetargetMap>,
+    pub autoplay: bool,
+}
+
+impl TrackAnimation {
+    pub fn new(track: Handle<Track>) -> Self {
+        Self {
+            track,
+            retarget: None,
+            autoplay: true,
+        }
+    }
+}
+
+/// Added once the clip is built; play `node` on the entity's `AnimationPlayer`.
+#[derive(Component, Clone, Debug)]
+pub struct TrackClip {
+    pub clip: Handle<AnimationClip>,
+    pub node: AnimationNodeIndex,
+}
+
+// The clip couldn't be built; retried only once the track or `TrackAnimation` changes
+#[derive(Component)]
+struct TrackClipFailed;
+
+type TrackAnimationQuery<'a> = (
+    Entity,
+    Ref<'a, TrackAnimation>,
+    &'a MorphWeights,
+    Option<&'a Name>,
+    Option<&'a TrackClip>,
+    Has<TrackClipFailed>,
+);
+
+fn build_track_clips(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<Track>>,
+    animations: Query<TrackAnimationQuery>,
+    tracks: Res<Assets<Track>>,
+    meshes: Res<Assets<Mesh>>,
+    mut clips: ResMut<Assets<AnimationClip>>,
+    mut graphs: ResMut<Assets<AnimationGraph>>,
+) {
+    let modified: Vec<_> = events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+
+    for (entity, animation, morph_weights, name, track_clip, failed) in &animations {
+        let needs_build = match track_clip {
+            None if !failed => true,
+            _ => animation.is_changed() || modified.contains(&animation.track.id()),
+        };
+        if !needs_build {
+            continue;
+        }
+
+        let Some(track) = tracks.get(&animation.track) else {
+            continue;
+        };
+        let Some(morph_names) = morph_weights
+            .first_mesh()
+            .and_then(|mesh| meshes.get(mesh))
+            .and_then(|mesh| mesh.morph_target_names())
+        else {
+            continue;
+        };
+
+        let retarget = animation
+            .retarget
+            .clone()
+            .unwrap_or_else(|| RetargetMap::pose_suffix(&track.rig));
+        let binding = retarget.bind(&track.rig, morph_names);
+
+        let target = AnimationTargetId::from_name(name.unwrap_or(&Name::new("sg-com")));
+        let clip = match track_to_clip(track, &binding, target) {
+            Ok(clip) => clip,
+            Err(e) => {
+                log::warn!("Failed to build a clip from the track for {entity}: {e}");
+                commands.entity(entity).insert(TrackClipFailed);
+                continue;
+            }
+        };
+        commands.entity(entity).remove::<TrackClipFailed>();
+
+        // Rebuilds reuse the existing clip so the player keeps its place
+        if let Some(track_clip) = track_clip {
+            if let Some(existing) = clips.get_mut(&track_clip.clip) {
+                *existing = clip;
+                continue;
+            }
+        }
+
+        let clip = clips.add(clip);
+        let (graph, node) = AnimationGraph::from_clip(clip.clone());
+        let mut player = AnimationPlayer::default();
+        if animation.autoplay {
+            player.play(node);
+        }
+
+        commands.entity(entity).insert((
+            AnimationTarget {
+                id: target,
+                player: entity,
+            },
+            player,
+            AnimationGraphHandle(graphs.add(graph)),
+            TrackClip { clip, node },
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::{NodeType, Rig, RigNode};
+    use std::time::{Duration, Instant};
+
+    fn track(frames: usize) -> Track {
+        let rig = Rig {
+            nodes: vec![RigNode {
+                name: "blendBoard".to_string(),
+                node_type: NodeType::BlendShape,
+                channels: vec!["jawOpen".to_string()],
+            }],
+        };
+        let mut track = Track::new(rig, 30.0);
+        for i in 0..frames {
+            track.push((i + 1) as f32 / 30.0, vec![vec![i as f32]]);
+        }
+        track
+    }
+
+    fn binding(track: &Track, morph_names: &[&str]) -> MorphBinding {
+        let morph_names: Vec<String> = morph_names.iter().map(|name| name.to_string()).collect();
+        RetargetMap::pose_suffix(&track.rig).bind(&track.rig, &morph_names)
+    }
+
+    #[test]
+    fn builds_a_weights_clip() {
+        let track = track(3);
+        let target = AnimationTargetId::from_name(&Name::new("Face"));
+        let clip = track_to_clip(&track, &binding(&track, &["jawOpen_pose"]), target).unwrap();
+        assert_eq!(clip.duration(), 3.0 / 30.0);
+        assert_eq!(clip.curves_for_target(target).map(Vec::len), Some(1));
+    }
+
+    #[test]
+    fn reports_why_no_clip_was_built() {
+        let target = AnimationTargetId::from_name(&Name::new("Face"));
+        let (long, short) = (track(3), track(1));
+        assert!(matches!(
+            track_to_clip(&long, &binding(&long, &[]), target),
+            Err(TrackClipError::Unbound)
+        ));
+        assert!(matches!(
+            track_to_clip(&short, &binding(&short, &["jawOpen_pose"]), target),
+            Err(TrackClipError::Curve(_))
+        ));
+    }
+
+    #[test]
+    fn loads_track_json() {
+        let dir = std::env::temp_dir().join(format!("sg-com-track-loader-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let track = track(2);
+        let mut json = Vec::new();
+        export::json::write(&track, &mut json).unwrap();
+        std::fs::write(dir.join("hello.track.json"), json).unwrap();
+
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin {
+                file_path: dir.to_string_lossy().into_owned(),
+                ..default()
+            },
+        ))
+        .init_asset::<Track>()
+        .init_asset_loader::<TrackLoader>();
+        let handle: Handle<Track> = app
+            .world()
+            .resource::<AssetServer>()
+            .load("hello.track.json");
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while app
+            .world()
+            .resource::<Assets<Track>>()
+            .get(&handle)
+            .is_none()
+        {
+            assert!(Instant::now() < deadline, "track never loaded");
+            app.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            app.world().resource::<Assets<Track>>().get(&handle),
+            Some(&track)
+        );
+    }
+}
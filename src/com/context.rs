@@ -1,10 +1,10 @@
 use super::{
     bindings::{
-        SG_AnimationNodeInfo, SG_AnimationType, SG_Error, SG_GetAnimationChannelName,
-        SG_GetAnimationNodeInfo, SG_GetOutputTraits, SG_GetVersionNumber, SG_GetVersionString,
-        SG_Initialize, SG_InputTraits, SG_OutputDataType, SG_OutputTraits,
-        SG_STDLN_CreateTransceiver, SG_SampleRate, SG_SampleType, SG_SetIntensity, SG_Shutdown,
-        SG_TransceiverPtr, ALGORITHM_DATA, CHARACTER_DATA,
+        SG_AnimationNodeInfo, SG_AnimationNodeType, SG_AnimationType, SG_Error,
+        SG_GetAnimationChannelName, SG_GetAnimationNodeInfo, SG_GetOutputTraits,
+        SG_GetVersionNumber, SG_GetVersionString, SG_Initialize, SG_InputTraits, SG_OutputDataType,
+        SG_OutputTraits, SG_STDLN_CreateTransceiver, SG_SampleRate, SG_SampleType, SG_SetIntensity,
+        SG_Shutdown, SG_TransceiverPtr, ALGORITHM_DATA, CHARACTER_DATA,
     },
     error::{Error, Result},
     player::Player,
@@ -29,6 +29,24 @@ pub struct AnimationNodeInfo {
     pub(super) channel_names: Vec<String>,
 }
 
+impl AnimationNodeInfo {
+    pub fn name(&self) -> String {
+        self.imp.name()
+    }
+
+    pub fn node_type(&self) -> SG_AnimationNodeType {
+        self.imp.type_
+    }
+
+    pub fn channel_count(&self) -> u32 {
+        self.imp.channel_count
+    }
+
+    pub fn channel_names(&self) -> &[String] {
+        &self.channel_names
+    }
+}
+
 static INITIALIZE_CODE: LazyLock<Result<()>> =
     LazyLock::new(|| unsafe { SG_Initialize() }.into_result());
 
@@ -46,6 +64,16 @@ impl SGContext {
         &self,
         sample_type: SG_SampleType,
         sample_rate: SG_SampleRate,
+    ) -> Result<Player> {
+        self.add_character_player(&self.character_data, sample_type, sample_rate)
+    }
+
+    /// Adds a player for another character's `.k` data, sharing this context's algorithm.
+    pub fn add_character_player(
+        &self,
+        character_data: &[u8],
+        sample_type: SG_SampleType,
+        sample_rate: SG_SampleRate,
     ) -> Result<Player> {
         let mut input_traits = SG_InputTraits {
             sample_type,
@@ -54,7 +82,7 @@ impl SGContext {
         };
 
         let mut algorithm_data = self.algorithm_data.clone();
-        let mut character_data = self.character_data.clone();
+        let mut character_data = character_data.to_vec();
         let mut transceiver: SG_TransceiverPtr = std::ptr::null_mut();
         unsafe {
             SG_STDLN_CreateTransceiver(
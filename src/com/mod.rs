@@ -3,8 +3,9 @@ mod context;
 mod error;
 mod player;
 
-pub use bindings::{SG_SampleRate, SG_SampleType};
+pub use bindings::{SG_AnimationNodeType, SG_Error, SG_SampleRate, SG_SampleType};
 pub use context::SGContext;
+pub use error::{Error, Result};
 pub use player::Player;
 
 #[inline]
@@ -2,14 +2,18 @@ use bevy::log::warn;
 
 use super::{
     bindings::{
-        SG_AdvanceOutput, SG_Error, SG_GetOutputAnimation, SG_Input, SG_InputTraits,
-        SG_OutputTraits, SG_STDLN_DestroyTransceiver, SG_SampleRate, SG_SampleType,
+        SG_AdvanceOutput, SG_Error, SG_GetCurrentIntensity, SG_GetCurrentMood, SG_GetMoodList,
+        SG_GetOutputAnimation, SG_Input, SG_InputTraits, SG_OutputTraits,
+        SG_STDLN_DestroyTransceiver, SG_SampleRate, SG_SampleType, SG_SetIntensity, SG_SetMood,
         SG_TransceiverPtr,
     },
     context::AnimationNodeInfo,
-    error::Result,
+    error::{Error, Result},
+};
+use std::{
+    ffi::{c_char, CStr, CString},
+    sync::{Arc, Mutex},
 };
-use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Clone)]
 pub struct Player {
@@ -284,6 +288,41 @@ impl Player {
         self.imp.input_traits.sample_rate
     }
 
+    /// Moods supported by the character, e.g. for `set_mood`.
+    pub fn moods(&self) -> Result<Vec<String>> {
+        let mut list: [c_char; 4096] = [0; 4096];
+        unsafe { SG_GetMoodList(self.imp.transceiver, list.as_mut_ptr(), list.len()) }
+            .into_result()?;
+        Ok(c_string(&list)
+            .split([',', ';', '\n'])
+            .map(str::trim)
+            .filter(|mood| !mood.is_empty())
+            .map(String::from)
+            .collect())
+    }
+
+    pub fn mood(&self) -> Result<String> {
+        let mut mood: [c_char; 256] = [0; 256];
+        unsafe { SG_GetCurrentMood(self.imp.transceiver, mood.as_mut_ptr(), mood.len()) }
+            .into_result()?;
+        Ok(c_string(&mood))
+    }
+
+    pub fn set_mood(&self, mood: &str) -> Result<()> {
+        let mood = CString::new(mood).map_err(|_| Error::from(SG_Error::SG_ERROR_EXCEPTION))?;
+        unsafe { SG_SetMood(self.imp.transceiver, mood.as_ptr()) }.into_result()
+    }
+
+    pub fn intensity(&self) -> Result<f32> {
+        let mut intensity = 0.0;
+        unsafe { SG_GetCurrentIntensity(self.imp.transceiver, &mut intensity) }.into_result()?;
+        Ok(intensity)
+    }
+
+    pub fn set_intensity(&self, intensity: f32) -> Result<()> {
+        unsafe { SG_SetIntensity(self.imp.transceiver, intensity) }.into_result()
+    }
+
     // pub fn set_sample_rate(&self, sample_rate: SG_SampleRate) -> Result<()> {
     //     let mut new_traits = self.imp.input_traits.clone();
     //     new_traits.sample_rate = sample_rate;
@@ -291,6 +330,13 @@ impl Player {
     // }
 }
 
+fn c_string(buffer: &[c_char]) -> String {
+    let bytes = unsafe { &*(buffer as *const [c_char] as *const [u8]) };
+    CStr::from_bytes_until_nul(bytes)
+        .map(|string| string.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
 impl Drop for PlayerImpl {
     fn drop(&mut self) {
         unsafe { SG_STDLN_DestroyTransceiver(self.transceiver) };
@@ -0,0 +1,226 @@
+use crate::{
+    audio::{
+        devices::{AudioDevices, DeviceSelector},
+        monitor::MonitorSettings,
+    },
+    retarget::RetargetMap,
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+pub const DEFAULT_CONFIG: &str = "sg-com.ron";
+
+/// Scene and app settings for the viewer, read from RON (`.ron`) or JSON.
+/// Every field is optional in the file and can be overridden on the command line.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewerConfig {
+    pub asset_path: String,
+    /// glTF or VRM model, relative to `asset_path`
+    pub model: String,
+    pub camera: CameraConfig,
+    pub lighting: LightingConfig,
+    /// cpal host such as `WASAPI` or `JACK`; the platform default when unset
+    pub audio_host: Option<String>,
+    /// Microphone name or index, see `--list-devices`; the system default when unset
+    pub input_device: Option<String>,
+    /// Speaker name or index for monitoring the input
+    pub output_device: Option<String>,
+    pub monitor: MonitorSettings,
+    /// `.k` character relative to `asset_path`, in place of the built-in one
+    pub character: Option<String>,
+    /// Preset name or retarget file, see `RetargetMap::read_file`
+    pub retarget: Option<String>,
+    /// `retarget` once read; `None` for the default `pose` map
+    #[serde(skip)]
+    pub retarget_map: Option<RetargetMap>,
+    pub mood: Option<String>,
+    pub intensity: Option<f32>,
+    /// Runs without a window or renderer, only feeding outputs and recordings
+    pub headless: bool,
+    /// Updates per second in headless mode
+    pub frame_rate: f64,
+}
+
+impl Default for ViewerConfig {
+    fn default() -> Self {
+        Self {
+            asset_path: format!("{}/assets", env!("CARGO_MANIFEST_DIR")),
+            model: "miku.glb".to_string(),
+            camera: CameraConfig::default(),
+            lighting: LightingConfig::default(),
+            audio_host: None,
+            input_device: None,
+            output_device: None,
+            monitor: MonitorSettings::default(),
+            character: None,
+            retarget: None,
+            retarget_map: None,
+            mood: None,
+            intensity: None,
+            headless: false,
+            frame_rate: 60.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraConfig {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    /// Vertical field of view in degrees
+    pub fov: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 1.5, 0.5],
+            target: [0.0, 1.5, 0.0],
+            fov: 45.0,
+        }
+    }
+}
+
+impl CameraConfig {
+    pub fn transform(&self) -> Transform {
+        Transform::from_translation(self.position.into()).looking_at(self.target.into(), Vec3::Y)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LightingConfig {
+    pub ambient_brightness: f32,
+    pub illuminance: f32,
+    /// Directional light rotation as XYZ euler degrees
+    pub direction: [f32; 3],
+}
+
+impl Default for LightingConfig {
+    fn default() -> Self {
+        Self {
+            ambient_brightness: 100.0,
+            illuminance: 500.0,
+            direction: [0.0, 0.0, 90.0],
+        }
+    }
+}
+
+impl LightingConfig {
+    pub fn rotation(&self) -> Quat {
+        let [x, y, z] = self.direction;
+        Quat::from_euler(
+            EulerRot::XYZ,
+            x.to_radians(),
+            y.to_radians(),
+            z.to_radians(),
+        )
+    }
+}
+
+impl ViewerConfig {
+    pub fn audio_devices(&self) -> AudioDevices {
+        AudioDevices {
+            host: self.audio_host.clone(),
+            input: DeviceSelector::from_option(self.input_device.as_deref()),
+            output: DeviceSelector::from_option(self.output_device.as_deref()),
+        }
+    }
+
+    pub fn read_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let reader = BufReader::new(File::open(path)?);
+        if path.extension().is_some_and(|extension| extension == "ron") {
+            ron::de::from_reader(reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(serde_json::from_reader(reader)?)
+        }
+    }
+}
+
+/// Parses `x,y,z`.
+pub fn parse_vec3(value: &str) -> Option<[f32; 3]> {
+    let mut parts = value.split(',').map(|part| part.trim().parse::<f32>());
+    let vec = [
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+    ];
+    parts.next().is_none().then_some(vec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sg-com-config-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn reads_ron_and_json() {
+        let ron = temp_path("viewer.ron");
+        std::fs::write(
+            &ron,
+            r#"(model: "character.vrm", camera: (fov: 30.0), retarget: Some("arkit"))"#,
+        )
+        .unwrap();
+        let config = ViewerConfig::read_file(&ron).unwrap();
+        assert_eq!(config.model, "character.vrm");
+        assert_eq!(config.camera.fov, 30.0);
+        // Fields missing from the file keep their defaults
+        assert_eq!(config.camera.position, CameraConfig::default().position);
+        assert_eq!(config.retarget.as_deref(), Some("arkit"));
+        assert_eq!(config.retarget_map, None);
+
+        // Anything but `.ron` is JSON
+        let json = temp_path("viewer.json");
+        std::fs::write(
+            &json,
+            r#"{"headless": true, "frame_rate": 30.0, "monitor": {"muted": true}}"#,
+        )
+        .unwrap();
+        let config = ViewerConfig::read_file(&json).unwrap();
+        assert!(config.headless);
+        assert_eq!(config.frame_rate, 30.0);
+        assert!(config.monitor.muted);
+        assert_eq!(config.model, ViewerConfig::default().model);
+
+        std::fs::remove_file(ron).unwrap();
+        std::fs::remove_file(json).unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let ron = temp_path("malformed.ron");
+        std::fs::write(&ron, "(model: 3)").unwrap();
+        let error = ViewerConfig::read_file(&ron).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(ron).unwrap();
+
+        let missing = ViewerConfig::read_file(temp_path("missing.json")).unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn parses_vec3() {
+        assert_eq!(parse_vec3("0,1.5,-2"), Some([0.0, 1.5, -2.0]));
+        assert_eq!(parse_vec3(" 1, 2 ,3 "), Some([1.0, 2.0, 3.0]));
+    }
+
+    #[test]
+    fn rejects_malformed_vec3() {
+        assert_eq!(parse_vec3("1,2"), None);
+        assert_eq!(parse_vec3("1,2,3,4"), None);
+        assert_eq!(parse_vec3("1,two,3"), None);
+        assert_eq!(parse_vec3(""), None);
+    }
+}
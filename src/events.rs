@@ -0,0 +1,270 @@
+use crate::facial_anim::{
+    process_animators, process_data, AnimatorFault, FacialAnim, FacialAnimator,
+};
+use bevy::{prelude::*, utils::HashMap};
+use std::time::Duration;
+
+/// Sends the events below through `EventReader`s and triggers them for
+/// observers, targeting the animator's entity (untargeted for the app-wide one).
+/// Speech is detected from the input level, so it needs live or clip input.
+#[derive(Default)]
+pub struct LipSyncEventsPlugin {
+    pub voice: VoiceActivity,
+}
+
+impl Plugin for LipSyncEventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.voice.clone())
+            .add_event::<SpeechStarted>()
+            .add_event::<SpeechStopped>()
+            .add_event::<MoodChanged>()
+            .add_event::<AnimatorError>()
+            .add_event::<AudioDeviceLost>()
+            .add_systems(
+                PreUpdate,
+                send_events.after(process_data).after(process_animators),
+            );
+    }
+}
+
+/// `animator` is `None` for the app-wide `FacialAnim` in every event.
+#[derive(Event, Debug, Clone)]
+pub struct SpeechStarted {
+    pub animator: Option<Entity>,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct SpeechStopped {
+    pub animator: Option<Entity>,
+    pub duration: Duration,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct MoodChanged {
+    pub animator: Option<Entity>,
+    pub previous: Option<String>,
+    pub mood: String,
+}
+
+/// SG_Com or an audio stream failed.
+#[derive(Event, Debug, Clone)]
+pub struct AnimatorError {
+    pub animator: Option<Entity>,
+    pub message: String,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct AudioDeviceLost {
+    pub animator: Option<Entity>,
+    pub message: String,
+}
+
+/// Voice activity thresholds on the input's RMS level. Speech starts once the
+/// level stays above `start_level` for `attack` and stops once it stays below
+/// `stop_level` for `release`.
+#[derive(Resource, Debug, Clone)]
+pub struct VoiceActivity {
+    pub start_level: f32,
+    pub stop_level: f32,
+    pub attack: Duration,
+    pub release: Duration,
+}
+
+impl Default for VoiceActivity {
+    fn default() -> Self {
+        Self {
+            start_level: 0.02,
+            stop_level: 0.01,
+            attack: Duration::from_millis(50),
+            release: Duration::from_millis(300),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VoiceChange {
+    Started,
+    Stopped(Duration),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VoiceDetector {
+    speaking: bool,
+    since: Duration,
+    // When the level first crossed the threshold for the pending change
+    pending: Option<Duration>,
+}
+
+impl VoiceDetector {
+    pub fn is_speaking(&self) -> bool {
+        self.speaking
+    }
+
+    pub fn update(
+        &mut self,
+        settings: &VoiceActivity,
+        level: f32,
+        now: Duration,
+    ) -> Option<VoiceChange> {
+        let (crossed, hold) = if self.speaking {
+            (level < settings.stop_level, settings.release)
+        } else {
+            (level >= settings.start_level, settings.attack)
+        };
+        if !crossed {
+            self.pending = None;
+            return None;
+        }
+
+        let pending = *self.pending.get_or_insert(now);
+        if now.saturating_sub(pending) < hold {
+            return None;
+        }
+
+        self.pending = None;
+        self.speaking = !self.speaking;
+        if self.speaking {
+            self.since = pending;
+            Some(VoiceChange::Started)
+        } else {
+            Some(VoiceChange::Stopped(pending.saturating_sub(self.since)))
+        }
+    }
+}
+
+#[derive(Default)]
+struct Watch {
+    voice: VoiceDetector,
+    mood: Option<String>,
+}
+
+fn emit<E: Event + Clone>(commands: &mut Commands, animator: Option<Entity>, event: E) {
+    commands.send_event(event.clone());
+    match animator {
+        Some(entity) => commands.trigger_targets(event, entity),
+        None => commands.trigger(event),
+    }
+}
+
+fn send_events(
+    mut commands: Commands,
+    mut watches: Local<HashMap<Option<Entity>, Watch>>,
+    settings: Res<VoiceActivity>,
+    time: Res<Time>,
+    anim: Res<FacialAnim>,
+    animators: Query<(Entity, &FacialAnimator)>,
+) {
+    watches.retain(|animator, _| animator.is_none_or(|entity| animators.contains(entity)));
+
+    let now = time.elapsed();
+    let all = std::iter::once((None, &**anim)).chain(
+        animators
+            .iter()
+            .map(|(entity, animator)| (Some(entity), animator)),
+    );
+    for (entity, animator) in all {
+        let watch = watches.entry(entity).or_default();
+
+        match watch.voice.update(&settings, animator.take_level(), now) {
+            Some(VoiceChange::Started) => {
+                emit(&mut commands, entity, SpeechStarted { animator: entity })
+            }
+            Some(VoiceChange::Stopped(duration)) => emit(
+                &mut commands,
+                entity,
+                SpeechStopped {
+                    animator: entity,
+                    duration,
+                },
+            ),
+            None => {}
+        }
+
+        if let Some(mood) = &animator.mood {
+            if watch.mood.as_ref() != Some(mood) {
+                let previous = watch.mood.replace(mood.clone());
+                emit(
+                    &mut commands,
+                    entity,
+                    MoodChanged {
+                        animator: entity,
+                        previous,
+                        mood: mood.clone(),
+                    },
+                );
+            }
+        }
+
+        for fault in animator.drain_faults() {
+            match fault {
+                AnimatorFault::DeviceLost(message) => emit(
+                    &mut commands,
+                    entity,
+                    AudioDeviceLost {
+                        animator: entity,
+                        message,
+                    },
+                ),
+                AnimatorFault::Player(message) | AnimatorFault::Stream(message) => emit(
+                    &mut commands,
+                    entity,
+                    AnimatorError {
+                        animator: entity,
+                        message,
+                    },
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn starts_after_the_attack_and_stops_after_the_release() {
+        let settings = VoiceActivity::default();
+        let mut voice = VoiceDetector::default();
+
+        assert_eq!(voice.update(&settings, 0.05, ms(1000)), None);
+        assert_eq!(voice.update(&settings, 0.05, ms(1040)), None);
+        assert_eq!(
+            voice.update(&settings, 0.05, ms(1050)),
+            Some(VoiceChange::Started)
+        );
+        assert!(voice.is_speaking());
+
+        // Between the thresholds keeps speaking
+        assert_eq!(voice.update(&settings, 0.015, ms(1500)), None);
+        assert_eq!(voice.update(&settings, 0.0, ms(2000)), None);
+        assert_eq!(
+            voice.update(&settings, 0.0, ms(2300)),
+            Some(VoiceChange::Stopped(ms(1000)))
+        );
+        assert!(!voice.is_speaking());
+    }
+
+    #[test]
+    fn ignores_blips_shorter_than_the_hold() {
+        let settings = VoiceActivity::default();
+        let mut voice = VoiceDetector::default();
+
+        assert_eq!(voice.update(&settings, 0.05, ms(0)), None);
+        assert_eq!(voice.update(&settings, 0.0, ms(30)), None);
+        assert_eq!(voice.update(&settings, 0.05, ms(60)), None);
+        assert_eq!(voice.update(&settings, 0.05, ms(100)), None);
+        assert!(!voice.is_speaking());
+
+        voice.update(&settings, 0.05, ms(110));
+        assert!(voice.is_speaking());
+        assert_eq!(voice.update(&settings, 0.0, ms(200)), None);
+        assert_eq!(voice.update(&settings, 0.05, ms(400)), None);
+        assert_eq!(voice.update(&settings, 0.0, ms(600)), None);
+        assert!(voice.is_speaking());
+    }
+}
@@ -0,0 +1,103 @@
+use crate::track::Track;
+use std::{
+    fs::File,
+    io::{BufWriter, Result, Write},
+    path::Path,
+};
+
+// Quotes a field if it would otherwise break the row
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Writes one row per frame: `time` followed by a `<node>/<channel>` column
+/// for every channel in the rig.
+pub fn write<W: Write>(track: &Track, mut writer: W) -> Result<()> {
+    write!(writer, "time")?;
+    for (node, channel) in track.rig.channel_paths() {
+        write!(writer, ",{}", escape(&format!("{node}/{channel}")))?;
+    }
+    writeln!(writer)?;
+
+    for frame in &track.frames {
+        write!(writer, "{}", frame.time)?;
+        // Nodes SG_Com returned no data for are left blank
+        for value in track.rig.aligned_values(&frame.values) {
+            match value {
+                Some(value) => write!(writer, ",{value}")?,
+                None => write!(writer, ",")?,
+            }
+        }
+        writeln!(writer)?;
+    }
+
+    writer.flush()
+}
+
+pub fn write_file(track: &Track, path: impl AsRef<Path>) -> Result<()> {
+    write(track, BufWriter::new(File::create(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::{NodeType, Rig, RigNode};
+
+    fn node(name: &str, channels: &[&str]) -> RigNode {
+        RigNode {
+            name: name.to_string(),
+            node_type: NodeType::Control,
+            channels: channels.iter().map(|channel| channel.to_string()).collect(),
+        }
+    }
+
+    fn write_string(track: &Track) -> String {
+        let mut out = Vec::new();
+        write(track, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn writes_header_and_rows() {
+        let rig = Rig {
+            nodes: vec![node("jaw", &["open"]), node("lips", &["pucker", "smile"])],
+        };
+        let mut track = Track::new(rig, 30.0);
+        track.push(0.0, vec![vec![0.5], vec![0.25, 1.0]]);
+
+        assert_eq!(
+            write_string(&track),
+            "time,jaw/open,lips/pucker,lips/smile\n0,0.5,0.25,1\n"
+        );
+    }
+
+    #[test]
+    fn empty_node_leaves_its_own_columns_blank() {
+        let rig = Rig {
+            nodes: vec![
+                node("jaw", &["open"]),
+                node("tongue", &["out", "up"]),
+                node("lips", &["smile"]),
+            ],
+        };
+        let mut track = Track::new(rig, 30.0);
+        track.push(0.0, vec![vec![0.5], Vec::new(), vec![1.0]]);
+
+        let csv = write_string(&track);
+        assert_eq!(csv.lines().nth(1), Some("0,0.5,,,1"));
+    }
+
+    #[test]
+    fn quotes_names_with_commas() {
+        let rig = Rig {
+            nodes: vec![node("a,b", &["c\"d"])],
+        };
+        let track = Track::new(rig, 30.0);
+
+        assert_eq!(write_string(&track), "time,\"a,b/c\"\"d\"\n");
+    }
+}
@@ -0,0 +1,494 @@
+use crate::{
+    retarget::{MorphBinding, RetargetMap},
+    track::Track,
+};
+use base64::Engine;
+use serde_json::{json, Value};
+use std::{
+    fs,
+    io::{Error, ErrorKind, Result},
+    path::Path,
+};
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: u32 = 0x4E4F534A;
+const CHUNK_BIN: u32 = 0x004E4942;
+
+const COMPONENT_FLOAT: u32 = 5126;
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, message.into())
+}
+
+/// A glTF document kept as raw JSON so unknown extensions survive a round trip.
+#[derive(Debug, Clone)]
+pub struct GltfDocument {
+    pub json: Value,
+    // Contents of the GLB-embedded buffer (always buffer 0 when present)
+    pub bin: Option<Vec<u8>>,
+}
+
+impl GltfDocument {
+    pub fn new() -> Self {
+        Self {
+            json: json!({
+                "asset": {
+                    "version": "2.0",
+                    "generator": concat!("sg-com ", env!("CARGO_PKG_VERSION")),
+                },
+            }),
+            bin: None,
+        }
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_slice(&fs::read(path)?)
+    }
+
+    pub fn from_slice(data: &[u8]) -> Result<Self> {
+        if !data.starts_with(GLB_MAGIC) {
+            return Ok(Self {
+                json: serde_json::from_slice(data)?,
+                bin: None,
+            });
+        }
+
+        let read_u32 = |offset: usize| -> Result<u32> {
+            data.get(offset..offset + 4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                .ok_or_else(|| invalid("Truncated GLB"))
+        };
+
+        if read_u32(4)? != GLB_VERSION {
+            return Err(invalid("Unsupported GLB version"));
+        }
+        let length = (read_u32(8)? as usize).min(data.len());
+
+        let mut json = None;
+        let mut bin = None;
+        let mut offset = 12;
+        while offset + 8 <= length {
+            let chunk_length = read_u32(offset)? as usize;
+            let chunk_type = read_u32(offset + 4)?;
+            let chunk = data
+                .get(offset + 8..offset + 8 + chunk_length)
+                .ok_or_else(|| invalid("Truncated GLB chunk"))?;
+            match chunk_type {
+                CHUNK_JSON => json = Some(serde_json::from_slice(chunk)?),
+                CHUNK_BIN if bin.is_none() => bin = Some(chunk.to_vec()),
+                _ => {}
+            }
+            offset += 8 + chunk_length;
+        }
+
+        Ok(Self {
+            json: json.ok_or_else(|| invalid("GLB has no JSON chunk"))?,
+            bin,
+        })
+    }
+
+    fn array(&mut self, key: &str) -> &mut Vec<Value> {
+        let root = self.json.as_object_mut().expect("glTF root is an object");
+        let value = root.entry(key).or_insert_with(|| json!([]));
+        if !value.is_array() {
+            *value = json!([]);
+        }
+        value.as_array_mut().unwrap()
+    }
+
+    fn push(&mut self, key: &str, value: Value) -> usize {
+        let array = self.array(key);
+        array.push(value);
+        array.len() - 1
+    }
+
+    // Buffer 0 has to be the embedded one, so make room for it if the source had none
+    fn embedded_buffer(&mut self) -> &mut Vec<u8> {
+        if self.bin.is_none() {
+            for view in self.array("bufferViews") {
+                if let Some(buffer) = view.get_mut("buffer") {
+                    *buffer = json!(buffer.as_u64().unwrap_or(0) + 1);
+                }
+            }
+            self.array("buffers").insert(0, json!({ "byteLength": 0 }));
+            self.bin = Some(Vec::new());
+        }
+        self.bin.as_mut().unwrap()
+    }
+
+    /// Appends `data` to the embedded buffer and returns a new buffer view over it.
+    pub fn push_buffer_view(&mut self, data: &[u8]) -> usize {
+        let bin = self.embedded_buffer();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+        let offset = bin.len();
+        bin.extend_from_slice(data);
+        let length = bin.len();
+
+        self.array("buffers")[0]["byteLength"] = json!(length);
+        self.push(
+            "bufferViews",
+            json!({
+                "buffer": 0,
+                "byteOffset": offset,
+                "byteLength": data.len(),
+            }),
+        )
+    }
+
+    pub fn push_float_accessor(&mut self, values: &[f32], kind: &str, count: usize) -> usize {
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let view = self.push_buffer_view(&bytes);
+        self.push(
+            "accessors",
+            json!({
+                "bufferView": view,
+                "componentType": COMPONENT_FLOAT,
+                "count": count,
+                "type": kind,
+            }),
+        )
+    }
+
+    /// Finds every node whose name, or whose mesh's name, is `target`.
+    /// Returns the mesh index along with the nodes using it.
+    pub fn find_mesh(&self, target: &str) -> Option<(usize, Vec<usize>)> {
+        let nodes = self.json.get("nodes")?.as_array()?;
+        let meshes = self.json.get("meshes")?.as_array()?;
+        let name_of = |value: &Value| value.get("name").and_then(Value::as_str).map(str::to_owned);
+
+        let mesh_index = nodes
+            .iter()
+            .find(|node| name_of(node).as_deref() == Some(target))
+            .and_then(|node| node.get("mesh")?.as_u64())
+            .or_else(|| {
+                meshes
+                    .iter()
+                    .position(|mesh| name_of(mesh).as_deref() == Some(target))
+                    .map(|index| index as u64)
+            })? as usize;
+
+        let node_indices = nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.get("mesh").and_then(Value::as_u64) == Some(mesh_index as u64))
+            .map(|(index, _)| index)
+            .collect();
+
+        Some((mesh_index, node_indices))
+    }
+
+    // Morph target names as exported by Blender and read by Bevy
+    pub fn morph_target_names(&self, mesh_index: usize) -> Option<Vec<String>> {
+        self.json
+            .get("meshes")?
+            .get(mesh_index)?
+            .get("extras")?
+            .get("targetNames")?
+            .as_array()?
+            .iter()
+            .map(|name| name.as_str().map(str::to_owned))
+            .collect()
+    }
+
+    /// Adds an animation that keys the `weights` of `nodes` from the track.
+    pub fn push_animation(
+        &mut self,
+        name: &str,
+        track: &Track,
+        binding: &MorphBinding,
+        nodes: &[usize],
+    ) -> Result<usize> {
+        if track.frames.is_empty() {
+            return Err(invalid("Track has no frames"));
+        }
+
+        let times: Vec<f32> = track.frames.iter().map(|frame| frame.time).collect();
+        let weights: Vec<f32> = track
+            .frames
+            .iter()
+            .flat_map(|frame| binding.evaluate(&frame.values))
+            .collect();
+
+        let input = self.push_float_accessor(&times, "SCALAR", times.len());
+        self.array("accessors")[input]["min"] = json!([times[0]]);
+        self.array("accessors")[input]["max"] = json!([times[times.len() - 1]]);
+        let output = self.push_float_accessor(&weights, "SCALAR", weights.len());
+
+        let channels: Vec<Value> = nodes
+            .iter()
+            .map(|node| {
+                json!({
+                    "sampler": 0,
+                    "target": { "node": node, "path": "weights" },
+                })
+            })
+            .collect();
+
+        Ok(self.push(
+            "animations",
+            json!({
+                "name": name,
+                "samplers": [{ "input": input, "output": output, "interpolation": "LINEAR" }],
+                "channels": channels,
+            }),
+        ))
+    }
+
+    pub fn to_glb(&self) -> Result<Vec<u8>> {
+        let mut json = serde_json::to_vec(&self.json)?;
+        json.resize(json.len().next_multiple_of(4), b' ');
+
+        let mut bin = self.bin.clone().unwrap_or_default();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+
+        let mut length = 12 + 8 + json.len();
+        if self.bin.is_some() {
+            length += 8 + bin.len();
+        }
+
+        let mut glb = Vec::with_capacity(length);
+        glb.extend_from_slice(GLB_MAGIC);
+        glb.extend_from_slice(&GLB_VERSION.to_le_bytes());
+        glb.extend_from_slice(&(length as u32).to_le_bytes());
+        glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        glb.extend_from_slice(&CHUNK_JSON.to_le_bytes());
+        glb.extend_from_slice(&json);
+        if self.bin.is_some() {
+            glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+            glb.extend_from_slice(&CHUNK_BIN.to_le_bytes());
+            glb.extend_from_slice(&bin);
+        }
+        Ok(glb)
+    }
+
+    // Plain glTF can't hold a binary chunk, so it's inlined as a data URI
+    pub fn to_gltf(&self) -> Result<Vec<u8>> {
+        let mut json = self.json.clone();
+        if let Some(bin) = &self.bin {
+            json["buffers"][0]["uri"] = json!(format!(
+                "data:application/octet-stream;base64,{}",
+                base64::engine::general_purpose::STANDARD.encode(bin)
+            ));
+        }
+        Ok(serde_json::to_vec_pretty(&json)?)
+    }
+
+    /// Writes a `.glb` or `.gltf` depending on the extension.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let is_gltf = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("gltf"));
+        fs::write(
+            path,
+            if is_gltf {
+                self.to_gltf()?
+            } else {
+                self.to_glb()?
+            },
+        )
+    }
+}
+
+impl Default for GltfDocument {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds a file holding only the animation, plus a placeholder node named
+/// `target` carrying the mapped morph targets so the animation has something
+/// to bind to. Importers can retarget it onto the real mesh by node name.
+pub fn standalone(
+    track: &Track,
+    retarget: &RetargetMap,
+    target: &str,
+    animation_name: &str,
+) -> Result<GltfDocument> {
+    let morph_names = retarget.morph_names();
+    if morph_names.is_empty() {
+        return Err(invalid("Retarget map has no morph targets"));
+    }
+
+    let mut document = GltfDocument::new();
+
+    // A single degenerate triangle; every morph target shares the same zero offsets
+    let positions = document.push_float_accessor(&[0.0; 9], "VEC3", 3);
+    document.array("accessors")[positions]["min"] = json!([0.0, 0.0, 0.0]);
+    document.array("accessors")[positions]["max"] = json!([0.0, 0.0, 0.0]);
+    let targets: Vec<Value> = morph_names
+        .iter()
+        .map(|_| json!({ "POSITION": positions }))
+        .collect();
+
+    let mesh = document.push(
+        "meshes",
+        json!({
+            "name": target,
+            "primitives": [{ "attributes": { "POSITION": positions }, "targets": targets }],
+            "weights": vec![0.0; morph_names.len()],
+            "extras": { "targetNames": morph_names },
+        }),
+    );
+    let node = document.push("nodes", json!({ "name": target, "mesh": mesh }));
+    document.push("scenes", json!({ "nodes": [node] }));
+    document.json["scene"] = json!(0);
+
+    let binding = retarget.bind(&track.rig, &morph_names);
+    document.push_animation(animation_name, track, &binding, &[node])?;
+    Ok(document)
+}
+
+/// Adds the animation to an existing model, targeting the mesh (or node) named `target`.
+pub fn merge(
+    mut document: GltfDocument,
+    track: &Track,
+    retarget: &RetargetMap,
+    target: &str,
+    animation_name: &str,
+) -> Result<GltfDocument> {
+    let (mesh_index, nodes) = document
+        .find_mesh(target)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("No mesh named {target}")))?;
+    let morph_names = document
+        .morph_target_names(mesh_index)
+        .ok_or_else(|| invalid(format!("Mesh {target} has no named morph targets")))?;
+
+    let binding = retarget.bind(&track.rig, &morph_names);
+    document.push_animation(animation_name, track, &binding, &nodes)?;
+    Ok(document)
+}
+
+pub fn write_standalone(
+    track: &Track,
+    retarget: &RetargetMap,
+    target: &str,
+    animation_name: &str,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    standalone(track, retarget, target, animation_name)?.write(path)
+}
+
+pub fn write_merged(
+    track: &Track,
+    retarget: &RetargetMap,
+    model_path: impl AsRef<Path>,
+    target: &str,
+    animation_name: &str,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    let document = GltfDocument::read(model_path)?;
+    merge(document, track, retarget, target, animation_name)?.write(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::{NodeType, Rig, RigNode};
+
+    fn track() -> Track {
+        let rig = Rig {
+            nodes: vec![RigNode {
+                name: "blendBoard".to_string(),
+                node_type: NodeType::BlendShape,
+                channels: vec!["jawOpen".to_string(), "mouthSmile".to_string()],
+            }],
+        };
+        let mut track = Track::new(rig, 30.0);
+        track.push(1.0 / 30.0, vec![vec![0.25, 0.5]]);
+        track.push(2.0 / 30.0, vec![vec![1.0, 0.0]]);
+        track
+    }
+
+    fn floats(document: &GltfDocument, accessor: &Value) -> Vec<f32> {
+        let view = &document.json["bufferViews"][accessor["bufferView"].as_u64().unwrap() as usize];
+        assert_eq!(view["buffer"], 0);
+        let offset = view["byteOffset"].as_u64().unwrap() as usize;
+        let length = view["byteLength"].as_u64().unwrap() as usize;
+        document.bin.as_ref().unwrap()[offset..offset + length]
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect()
+    }
+
+    // The animation's only sampler, as (times, weights)
+    fn sampler(document: &GltfDocument, animation: &Value) -> (Vec<f32>, Vec<f32>) {
+        let sampler = &animation["samplers"][0];
+        let accessor =
+            |key: &str| &document.json["accessors"][sampler[key].as_u64().unwrap() as usize];
+        (
+            floats(document, accessor("input")),
+            floats(document, accessor("output")),
+        )
+    }
+
+    #[test]
+    fn writes_a_standalone_glb() {
+        let track = track();
+        let retarget = RetargetMap::pose_suffix(&track.rig);
+        let glb = standalone(&track, &retarget, "Face", "hello")
+            .unwrap()
+            .to_glb()
+            .unwrap();
+        let document = GltfDocument::from_slice(&glb).unwrap();
+
+        let (mesh, nodes) = document.find_mesh("Face").unwrap();
+        assert_eq!(
+            document.morph_target_names(mesh).unwrap(),
+            ["jawOpen_pose", "mouthSmile_pose"]
+        );
+
+        let animation = &document.json["animations"][0];
+        assert_eq!(animation["name"], "hello");
+        assert_eq!(
+            animation["channels"][0]["target"],
+            json!({ "node": nodes[0], "path": "weights" })
+        );
+        let (times, weights) = sampler(&document, animation);
+        assert_eq!(times, [1.0 / 30.0, 2.0 / 30.0]);
+        assert_eq!(weights, [0.25, 0.5, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn merges_into_a_model_with_an_external_buffer() {
+        let mut model = GltfDocument::new();
+        model.json["buffers"] = json!([{ "uri": "model.bin", "byteLength": 12 }]);
+        model.json["bufferViews"] = json!([{ "buffer": 0, "byteLength": 12 }]);
+        model.json["meshes"] = json!([{
+            "name": "Head",
+            "primitives": [],
+            "extras": { "targetNames": ["mouthSmile_pose", "unused", "jawOpen_pose"] },
+        }]);
+        model.json["nodes"] = json!([{ "name": "Root" }, { "name": "Face", "mesh": 0 }]);
+
+        let track = track();
+        let retarget = RetargetMap::pose_suffix(&track.rig);
+        let merged = merge(model, &track, &retarget, "Face", "hello").unwrap();
+        let document = GltfDocument::from_slice(&merged.to_glb().unwrap()).unwrap();
+
+        // The model's own buffer moves up to make room for the embedded one
+        assert_eq!(document.json["bufferViews"][0]["buffer"], 1);
+        assert_eq!(document.json["buffers"][1]["uri"], "model.bin");
+
+        let animation = &document.json["animations"][0];
+        assert_eq!(
+            animation["channels"],
+            json!([{ "sampler": 0, "target": { "node": 1, "path": "weights" } }])
+        );
+        let (_, weights) = sampler(&document, animation);
+        assert_eq!(weights, [0.5, 0.0, 0.25, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn refuses_a_mesh_without_morph_target_names() {
+        let mut model = GltfDocument::new();
+        model.json["meshes"] = json!([{ "name": "Face", "primitives": [] }]);
+        model.json["nodes"] = json!([{ "name": "Face", "mesh": 0 }]);
+
+        let track = track();
+        let retarget = RetargetMap::pose_suffix(&track.rig);
+        assert!(merge(model.clone(), &track, &retarget, "Face", "hello").is_err());
+        assert!(merge(model, &track, &retarget, "Body", "hello").is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use crate::track::Track;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Result, Write},
+    path::Path,
+};
+
+/// Writes the track along with its rig metadata (node names, types and
+/// channels), frame rate and the SG_Com version it was baked with.
+pub fn write<W: Write>(track: &Track, mut writer: W) -> Result<()> {
+    serde_json::to_writer(&mut writer, track)?;
+    writer.flush()
+}
+
+pub fn write_pretty<W: Write>(track: &Track, mut writer: W) -> Result<()> {
+    serde_json::to_writer_pretty(&mut writer, track)?;
+    writer.flush()
+}
+
+pub fn write_file(track: &Track, path: impl AsRef<Path>) -> Result<()> {
+    write(track, BufWriter::new(File::create(path)?))
+}
+
+pub fn from_slice(data: &[u8]) -> Result<Track> {
+    Ok(serde_json::from_slice(data)?)
+}
+
+pub fn read<R: Read>(reader: R) -> Result<Track> {
+    Ok(serde_json::from_reader(reader)?)
+}
+
+pub fn read_file(path: impl AsRef<Path>) -> Result<Track> {
+    read(BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::{NodeType, Rig, RigNode};
+
+    #[test]
+    fn round_trips() {
+        let rig = Rig {
+            nodes: vec![RigNode {
+                name: "blendBoard".to_string(),
+                node_type: NodeType::BlendShape,
+                channels: vec!["jawOpen".to_string(), "mouthSmile".to_string()],
+            }],
+        };
+        let mut track = Track::new(rig, 30.0);
+        track.sg_version = Some("2.0".to_string());
+        track.push(0.0, vec![vec![0.0, 0.5]]);
+        track.push(1.0 / 30.0, vec![Vec::new()]);
+
+        let mut out = Vec::new();
+        write(&track, &mut out).unwrap();
+        assert_eq!(from_slice(&out).unwrap(), track);
+
+        let mut pretty = Vec::new();
+        write_pretty(&track, &mut pretty).unwrap();
+        assert_eq!(read(pretty.as_slice()).unwrap(), track);
+    }
+
+    #[test]
+    fn reads_nan_back_from_null() {
+        let rig = Rig {
+            nodes: vec![RigNode {
+                name: "blendBoard".to_string(),
+                node_type: NodeType::BlendShape,
+                channels: vec!["jawOpen".to_string(), "mouthSmile".to_string()],
+            }],
+        };
+        let mut track = Track::new(rig, 30.0);
+        track.push(0.0, vec![vec![f32::NAN, 0.5]]);
+
+        let mut out = Vec::new();
+        write(&track, &mut out).unwrap();
+        assert!(String::from_utf8_lossy(&out).contains("[null,0.5]"));
+
+        let read = from_slice(&out).unwrap();
+        assert!(read.frames[0].values[0][0].is_nan());
+        assert_eq!(read.frames[0].values[0][1], 0.5);
+    }
+}
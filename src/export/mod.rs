@@ -0,0 +1,4 @@
+pub mod csv;
+pub mod gltf;
+pub mod json;
+pub mod viseme;
@@ -0,0 +1,36 @@
+use crate::viseme::VisemeTrack;
+use std::{
+    fs::File,
+    io::{BufWriter, Result, Write},
+    path::Path,
+};
+
+/// Writes one row per dominant viseme: `start,end,id,viseme`.
+pub fn write_csv<W: Write>(track: &VisemeTrack, mut writer: W) -> Result<()> {
+    writeln!(writer, "start,end,id,viseme")?;
+    for event in &track.events {
+        writeln!(
+            writer,
+            "{},{},{},{}",
+            event.start, event.end, event.id, event.viseme
+        )?;
+    }
+    writer.flush()
+}
+
+/// Writes the viseme names, the dominant viseme events and every frame's weights.
+pub fn write_json<W: Write>(track: &VisemeTrack, mut writer: W) -> Result<()> {
+    serde_json::to_writer(&mut writer, track)?;
+    writer.flush()
+}
+
+/// Picks CSV or JSON from the extension.
+pub fn write_file(track: &VisemeTrack, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let writer = BufWriter::new(File::create(path)?);
+    if path.extension().is_some_and(|extension| extension == "csv") {
+        write_csv(track, writer)
+    } else {
+        write_json(track, writer)
+    }
+}
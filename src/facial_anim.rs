@@ -1,53 +1,607 @@
-use crate::com::{self, SGContext, SG_SampleRate, SG_SampleType};
+use crate::{
+    audio::monitor::{MonitorControl, MonitorSettings},
+    playback::TrackPlayback,
+    recorder::InputTap,
+    retarget::RetargetMap,
+    track::Rig,
+};
+#[cfg(feature = "runtime")]
+use crate::{
+    audio::{
+        devices::{self, AudioDevices, DeviceSelector, DeviceSnapshot, Direction},
+        monitor, output,
+    },
+    character::Character,
+    com::{self, SGContext, SG_SampleRate, SG_SampleType},
+};
 use bevy::{log, prelude::*};
+#[cfg(feature = "runtime")]
 use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-    SampleFormat, SampleRate, StreamConfig,
+    traits::{DeviceTrait, StreamTrait},
+    SampleFormat,
 };
-use crossbeam_deque::Worker;
+use cpal::{FromSample, Sample, StreamConfig};
+use crossbeam_deque::{Injector, Steal};
 use std::sync::Mutex;
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+};
+#[cfg(feature = "runtime")]
+use std::{sync::mpsc::Receiver, time::Duration};
 
-pub struct FacialAnimPlugin;
+pub struct FacialAnimPlugin {
+    /// Opened up front with `FacialAnimator::from_source`, so a track that
+    /// fails to load stops the app before it starts. Taken when built.
+    pub anim: Mutex<Option<FacialAnimator>>,
+    pub retarget: Option<RetargetMap>,
+    pub mood: Option<String>,
+    pub intensity: Option<f32>,
+    /// Applies when running live with an output device
+    pub monitor: MonitorSettings,
+}
 
 impl Plugin for FacialAnimPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(FacialAnim::new());
-        app.add_systems(PreUpdate, process_data);
+        let mut anim =
+            (self.anim.lock().unwrap().take()).expect("FacialAnimPlugin is only built once");
+        if let Some(retarget) = &self.retarget {
+            anim.set_retarget(retarget.clone());
+        }
+        if let Some(mood) = &self.mood {
+            anim.set_mood(mood);
+        }
+        if let Some(intensity) = self.intensity {
+            anim.set_intensity(intensity);
+        }
+        if let Some(monitor) = anim.monitor() {
+            monitor.apply(&self.monitor);
+        }
+        app.insert_resource(FacialAnim(anim));
+        app.add_systems(PreUpdate, (process_data, process_animators));
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum AnimSource {
+    /// Microphone input processed by SG_Com
+    #[cfg(feature = "runtime")]
+    Live(AudioDevices),
+    /// A recorded or baked track file, played back without SG_Com
+    Playback(PathBuf),
+}
+
+/// The app-wide animator, which feeds the viewer model, recorder and outputs.
+#[derive(Resource, Deref, DerefMut)]
+pub struct FacialAnim(pub FacialAnimator);
+
+/// Drives every `MorphWeights` in its entity's hierarchy from its own source
+/// and SG player, see `binding::MorphBindingPlugin`.
+#[derive(Component)]
+pub struct FacialAnimator {
+    source: Source,
+    started: bool,
+    pub processed_data: Option<Vec<Vec<f32>>>,
+    pub rig: Rig,
+    pub sg_version: Option<String>,
+    /// Current SG mood and intensity, only known when running live
+    pub mood: Option<String>,
+    pub intensity: f32,
+    retarget: Option<RetargetMap>,
+    // Bumped on every retarget or rig change so bound meshes know to rebind
+    retarget_generation: u32,
+    /// Replaces the compiled-in character, see `character::CharacterPlugin`
+    #[cfg(feature = "runtime")]
+    character: Option<Handle<Character>>,
+    // The character the player was last built with
+    #[cfg(feature = "runtime")]
+    loaded_character: Option<AssetId<Character>>,
+    signals: Signals,
+}
+
+/// Something that went wrong while running, see `events::AnimatorError`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnimatorFault {
+    /// SG_Com failed to take input or produce output
+    Player(String),
+    /// An audio stream reported an error
+    Stream(String),
+    /// The input or output device went away
+    DeviceLost(String),
+}
+
+/// Peak RMS level of the input since it was last taken, written from audio callbacks.
+#[derive(Clone, Default)]
+pub struct LevelMeter(Arc<AtomicU32>);
+
+impl LevelMeter {
+    pub fn record<T>(&self, data: &[T])
+    where
+        T: Sample,
+        f32: FromSample<T>,
+    {
+        if data.is_empty() {
+            return;
+        }
+        let sum: f32 = data
+            .iter()
+            .map(|sample| {
+                let sample: f32 = sample.to_sample();
+                sample * sample
+            })
+            .sum();
+        let rms = (sum / data.len() as f32).sqrt();
+        // Non-negative floats order the same as their bits
+        self.0.fetch_max(rms.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn take(&self) -> f32 {
+        f32::from_bits(self.0.swap(0, Ordering::Relaxed))
     }
 }
 
-#[derive(Resource)]
-pub struct FacialAnim {
+// Shared with the audio callbacks
+#[derive(Clone, Default)]
+struct Signals {
+    level: LevelMeter,
+    faults: Arc<Injector<AnimatorFault>>,
+    // Set by stream errors so the live source rebuilds its streams
+    lost: Arc<AtomicBool>,
+}
+
+impl Signals {
+    fn fault(&self, fault: AnimatorFault) {
+        log::error!("{fault:?}");
+        self.faults.push(fault);
+    }
+}
+
+enum Source {
+    #[cfg(feature = "runtime")]
+    Live(LiveSource),
+    /// Fed by `lip_sync::LipSyncPlugin` from the entity's `AudioPlayer`
+    #[cfg(feature = "runtime")]
+    Clip(com::Player),
+    Playback(TrackPlayback),
+}
+
+/// Clips are downmixed and resampled to this rate before being fed to SG_Com.
+#[cfg(feature = "runtime")]
+pub const CLIP_SAMPLE_RATE: SG_SampleRate = SG_SampleRate::SG_RATE_16KHZ;
+
+/// How often a live source checks for changed default devices or retries missing ones.
+#[cfg(feature = "runtime")]
+pub const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+// Keeps live input running across device changes by rebuilding `input`
+#[cfg(feature = "runtime")]
+struct LiveSource {
+    devices: AudioDevices,
+    character: Option<Arc<[u8]>>,
+    // `None` while no input device can be opened
+    input: Option<LiveInput>,
+    // Shared across rebuilds so a recording keeps going
+    tap: InputTap,
+    monitor: MonitorControl,
+    playing: bool,
+    failed: bool,
+    // Reapplied to every new player
+    mood: Option<String>,
+    intensity: Option<f32>,
+    // Snapshots from a background thread, so polling devices never stalls a frame
+    watcher: Mutex<Receiver<DeviceSnapshot>>,
+}
+
+#[cfg(feature = "runtime")]
+struct LiveInput {
     context: &'static SGContext,
     player: com::Player,
     stream: Mutex<SendStream>,
-    out_stream: Mutex<SendStream>,
-    pub processed_data: Option<Vec<Vec<f32>>>,
-    pub names: Vec<(String, Vec<String>)>,
+    out_stream: Option<Mutex<SendStream>>,
+    config: StreamConfig,
+    input_name: String,
+    output_name: Option<String>,
+    // Whether the default stood in for a missing selected device
+    input_fallback: bool,
+    output_fallback: bool,
 }
 
-struct SendStream(cpal::Stream);
+pub(crate) struct SendStream(pub cpal::Stream);
 
 unsafe impl Send for SendStream {}
 
-impl FacialAnim {
+impl FacialAnimator {
+    fn with_source(source: Source, signals: Signals, rig: Rig, sg_version: Option<String>) -> Self {
+        Self {
+            source,
+            started: false,
+            processed_data: None,
+            rig,
+            sg_version,
+            mood: None,
+            intensity: 1.0,
+            retarget: None,
+            retarget_generation: 0,
+            #[cfg(feature = "runtime")]
+            character: None,
+            #[cfg(feature = "runtime")]
+            loaded_character: None,
+            signals,
+        }
+    }
+
+    #[cfg(feature = "runtime")]
     pub fn new() -> Self {
-        let ctx = com::context().expect("Failed to initialize SG_Com");
+        Self::live(AudioDevices::default())
+    }
 
-        let host = cpal::default_host();
-        let input = host
-            .default_input_device()
-            .expect("No input device available");
-        let input_config = input
-            .default_input_config()
-            .expect("No default input config available");
+    /// Listens to the selected devices, falling back to the defaults while
+    /// they're missing. Streams are rebuilt when a device goes away or the
+    /// default changes, and the rig is empty until an input device is found.
+    #[cfg(feature = "runtime")]
+    pub fn live(devices: AudioDevices) -> Self {
+        let signals = Signals::default();
+        let live = LiveSource::new(devices, &signals);
+        let rig = live
+            .input
+            .as_ref()
+            .map(|input| Rig::from_player(&input.player))
+            .unwrap_or_default();
+        Self::with_source(Source::Live(live), signals, rig, Some(SGContext::version()))
+    }
 
-        log::info!("Input device: {}", input.name().unwrap());
-        log::info!("Input config: {:?}", input_config);
+    /// An animator without a microphone, driven by in-game audio clips.
+    #[cfg(feature = "runtime")]
+    pub fn clip() -> com::Result<Self> {
+        let player =
+            com::context()?.add_player(SG_SampleType::SG_SAMPLE_FLOAT32, CLIP_SAMPLE_RATE)?;
+        let rig = Rig::from_player(&player);
+        Ok(Self::with_source(
+            Source::Clip(player),
+            Signals::default(),
+            rig,
+            Some(SGContext::version()),
+        ))
+    }
 
-        let output = host
-            .default_output_device()
-            .expect("No output device available");
+    pub fn playback(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let playback = TrackPlayback::open(path)?;
+        let rig = playback.track().rig.clone();
+        let sg_version = playback.track().sg_version.clone();
+        Ok(Self::with_source(
+            Source::Playback(playback),
+            Signals::default(),
+            rig,
+            sg_version,
+        ))
+    }
+
+    pub fn from_source(source: &AnimSource) -> std::io::Result<Self> {
+        match source {
+            #[cfg(feature = "runtime")]
+            AnimSource::Live(devices) => Ok(Self::live(devices.clone())),
+            AnimSource::Playback(path) => Self::playback(path),
+        }
+    }
+
+    pub fn with_retarget(mut self, retarget: RetargetMap) -> Self {
+        self.set_retarget(retarget);
+        self
+    }
+
+    /// Defaults to `RetargetMap::pose_suffix` for the rig.
+    pub fn retarget(&self) -> RetargetMap {
+        self.retarget
+            .clone()
+            .unwrap_or_else(|| RetargetMap::pose_suffix(&self.rig))
+    }
+
+    pub fn set_retarget(&mut self, retarget: RetargetMap) {
+        self.retarget = Some(retarget);
+        self.retarget_generation += 1;
+    }
+
+    /// Joints the retarget map drives; the default map has none.
+    pub fn bone_names(&self) -> Vec<String> {
+        self.retarget
+            .as_ref()
+            .map(RetargetMap::bone_names)
+            .unwrap_or_default()
+    }
+
+    /// Bumped whenever the rig or the retarget map changes, including when the
+    /// character is swapped; anything bound to the rig should rebuild on it.
+    pub fn retarget_generation(&self) -> u32 {
+        self.retarget_generation
+    }
+
+    pub fn is_live(&self) -> bool {
+        !matches!(self.source, Source::Playback(_))
+    }
+
+    #[cfg(feature = "runtime")]
+    pub fn with_character(mut self, character: Handle<Character>) -> Self {
+        self.set_character(character);
+        self
+    }
+
+    /// The player is rebuilt for it once it's loaded, and again whenever it changes.
+    #[cfg(feature = "runtime")]
+    pub fn set_character(&mut self, character: Handle<Character>) {
+        self.character = Some(character);
+    }
+
+    #[cfg(feature = "runtime")]
+    pub fn character(&self) -> Option<&Handle<Character>> {
+        self.character.as_ref()
+    }
+
+    #[cfg(feature = "runtime")]
+    pub(crate) fn loaded_character(&self) -> Option<AssetId<Character>> {
+        self.loaded_character
+    }
+
+    /// Rebuilds the SG player for another character, keeping the source's input.
+    #[cfg(feature = "runtime")]
+    pub(crate) fn load_character(
+        &mut self,
+        id: AssetId<Character>,
+        character: &Character,
+    ) -> com::Result<()> {
+        self.loaded_character = Some(id);
+        match &mut self.source {
+            Source::Live(live) => {
+                live.character = Some(character.data.clone());
+                live.rebuild(&self.signals);
+            }
+            Source::Clip(player) => {
+                *player = com::context()?.add_character_player(
+                    &character.data,
+                    SG_SampleType::SG_SAMPLE_FLOAT32,
+                    CLIP_SAMPLE_RATE,
+                )?;
+            }
+            Source::Playback(_) => {
+                log::warn!("Ignoring character: playback doesn't use SG_Com");
+                return Ok(());
+            }
+        }
+
+        if let Some(player) = self.player() {
+            self.rig = Rig::from_player(player);
+        }
+        self.processed_data = None;
+        self.retarget_generation += 1;
+        Ok(())
+    }
+
+    #[cfg(feature = "runtime")]
+    fn player(&self) -> Option<&com::Player> {
+        match &self.source {
+            Source::Live(live) => live.input.as_ref().map(|input| &input.player),
+            Source::Clip(player) => Some(player),
+            Source::Playback(_) => None,
+        }
+    }
+
+    /// Queues mono `CLIP_SAMPLE_RATE` samples; only clip animators accept input.
+    #[cfg(feature = "runtime")]
+    pub fn feed(&self, samples: &[f32]) -> com::Result<()> {
+        let Source::Clip(player) = &self.source else {
+            return Ok(());
+        };
+        // The player only flushes 10ms at a time, so the input is fed in matching chunks
+        let chunk = (CLIP_SAMPLE_RATE.to_rate() / 100) as usize;
+        self.signals.level.record(samples);
+        for samples in samples.chunks(chunk) {
+            player.add_input_float32(samples).inspect_err(|e| {
+                self.signals
+                    .faults
+                    .push(AnimatorFault::Player(e.to_string()))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Moods the live character supports; empty for playback.
+    pub fn moods(&self) -> Vec<String> {
+        #[cfg(feature = "runtime")]
+        if let Some(player) = self.player() {
+            return player.moods().unwrap_or_default();
+        }
+        Vec::new()
+    }
+
+    pub fn set_mood(&mut self, mood: &str) {
+        #[cfg(feature = "runtime")]
+        if self.is_live() {
+            if let Source::Live(live) = &mut self.source {
+                live.mood = Some(mood.to_string());
+            }
+            if let Some(Err(e)) = self.player().map(|player| player.set_mood(mood)) {
+                log::error!("Failed to set mood {mood}: {e}");
+            }
+            return;
+        }
+        log::warn!("Ignoring mood {mood}: moods are only available with live input");
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        #[cfg(feature = "runtime")]
+        if self.is_live() {
+            if let Source::Live(live) = &mut self.source {
+                live.intensity = Some(intensity);
+            }
+            if let Some(Err(e)) = self.player().map(|player| player.set_intensity(intensity)) {
+                log::error!("Failed to set intensity {intensity}: {e}");
+            }
+            return;
+        }
+        log::warn!("Ignoring intensity {intensity}: only available with live input");
+    }
+
+    /// Input level since the last call; always zero for playback.
+    pub fn take_level(&self) -> f32 {
+        self.signals.level.take()
+    }
+
+    /// Faults since the last call, oldest first.
+    pub fn drain_faults(&self) -> impl Iterator<Item = AnimatorFault> + '_ {
+        std::iter::from_fn(|| loop {
+            match self.signals.faults.steal() {
+                Steal::Success(fault) => return Some(fault),
+                Steal::Empty => return None,
+                Steal::Retry => continue,
+            }
+        })
+    }
+
+    /// The raw microphone tap and its format, when running live.
+    pub fn input(&self) -> Option<(&InputTap, &StreamConfig)> {
+        match &self.source {
+            #[cfg(feature = "runtime")]
+            Source::Live(live) => live.input.as_ref().map(|input| (&live.tap, &input.config)),
+            _ => None,
+        }
+    }
+
+    /// The raw microphone tap when running live, even while no input device is open.
+    pub fn tap(&self) -> Option<&InputTap> {
+        match &self.source {
+            #[cfg(feature = "runtime")]
+            Source::Live(live) => Some(&live.tap),
+            _ => None,
+        }
+    }
+
+    /// Mute and delay for the speaker monitor, when running live. Kept across device changes.
+    pub fn monitor(&self) -> Option<&MonitorControl> {
+        match &self.source {
+            #[cfg(feature = "runtime")]
+            Source::Live(live) => Some(&live.monitor),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "runtime")]
+impl LiveSource {
+    fn new(devices: AudioDevices, signals: &Signals) -> Self {
+        let mut live = Self {
+            character: None,
+            input: None,
+            tap: InputTap::default(),
+            monitor: MonitorControl::default(),
+            playing: false,
+            failed: false,
+            mood: None,
+            intensity: None,
+            watcher: Mutex::new(devices::watch(devices.clone(), DEVICE_CHECK_INTERVAL)),
+            devices,
+        };
+        live.rebuild(signals);
+        live
+    }
+
+    /// Replaces the streams and player, returning whether new ones could be built.
+    fn rebuild(&mut self, signals: &Signals) -> bool {
+        // Release the old devices before opening them again
+        self.input = None;
+        signals.lost.store(false, Ordering::Relaxed);
+
+        let input = LiveInput::new(
+            &self.devices,
+            self.character.as_deref(),
+            signals,
+            &self.tap,
+            &self.monitor,
+        )
+        .and_then(|input| {
+            if self.playing {
+                input.start()?;
+            }
+            Ok(input)
+        });
+        let input = match input {
+            Ok(input) => input,
+            Err(e) => {
+                // Retried every check, so only report the first failure
+                if !self.failed {
+                    signals.fault(AnimatorFault::Stream(format!(
+                        "Failed to open audio devices: {e}"
+                    )));
+                }
+                self.failed = true;
+                return false;
+            }
+        };
+
+        if self.failed {
+            log::info!("Audio devices recovered");
+        }
+        self.failed = false;
+        if let Some(mood) = &self.mood {
+            if let Err(e) = input.player.set_mood(mood) {
+                log::error!("Failed to set mood {mood}: {e}");
+            }
+        }
+        if let Some(intensity) = self.intensity {
+            if let Err(e) = input.player.set_intensity(intensity) {
+                log::error!("Failed to set intensity {intensity}: {e}");
+            }
+        }
+        self.input = Some(input);
+        true
+    }
+
+    fn start(&mut self, signals: &Signals) {
+        self.playing = true;
+        if let Some(Err(e)) = self.input.as_ref().map(LiveInput::start) {
+            signals.fault(AnimatorFault::Stream(e.to_string()));
+        }
+    }
+
+    /// Rebuilds after a stream error, once the default device changes or, while
+    /// the selected devices are missing, once they're back. Returns whether it did.
+    fn maintain(&mut self, signals: &Signals) -> bool {
+        if signals.lost.load(Ordering::Relaxed) {
+            log::warn!("Audio stream failed, reopening devices");
+            return self.rebuild(signals);
+        }
+
+        // Only the latest snapshot matters
+        let Some(snapshot) = self.watcher.get_mut().unwrap().try_iter().last() else {
+            return false;
+        };
+        let stale = match &self.input {
+            None => true,
+            Some(input) => input.is_stale(&self.devices, &snapshot),
+        };
+        stale && self.rebuild(signals)
+    }
+}
+
+#[cfg(feature = "runtime")]
+impl LiveInput {
+    fn new(
+        devices: &AudioDevices,
+        character_data: Option<&[u8]>,
+        signals: &Signals,
+        tap: &InputTap,
+        control: &MonitorControl,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let ctx = com::context()?;
+
+        let host = devices.host();
+        let (input, input_fallback) = select_device(&host, Direction::Input, &devices.input)?;
+        let input_config = input.default_input_config()?;
+        let input_name = input.name()?;
+
+        log::info!("Input device: {input_name} ({})", host.id().name());
+        log::info!("Input config: {:?}", input_config);
 
         let com_sample_type = match input_config.sample_format() {
             SampleFormat::I8 => SG_SampleType::SG_SAMPLE_PCM8,
@@ -55,143 +609,350 @@ impl FacialAnim {
             SampleFormat::I32 => SG_SampleType::SG_SAMPLE_PCM32,
             SampleFormat::F32 => SG_SampleType::SG_SAMPLE_FLOAT32,
             SampleFormat::F64 => SG_SampleType::SG_SAMPLE_FLOAT64,
-            _ => panic!("Unsupported sample format"),
+            format => return Err(format!("Unsupported sample format {format}").into()),
         };
         let com_sample_rate = SG_SampleRate::from_rate(input_config.sample_rate().0 as i32)
-            .expect("Unsupported sample rate");
-        let player = ctx
-            .add_player(com_sample_type, com_sample_rate)
-            .expect("Failed to add player");
+            .ok_or_else(|| format!("Unsupported sample rate {}", input_config.sample_rate().0))?;
+        let player = match character_data {
+            Some(character_data) => {
+                ctx.add_character_player(character_data, com_sample_type, com_sample_rate)
+            }
+            None => ctx.add_player(com_sample_type, com_sample_rate),
+        }?;
 
-        let err_fn = move |err| {
-            log::error!("Input stream error: {}", err);
+        let err_fn = |stream: &'static str| {
+            let signals = signals.clone();
+            move |err| {
+                signals.lost.store(true, Ordering::Relaxed);
+                signals.fault(match err {
+                    cpal::StreamError::DeviceNotAvailable => {
+                        AnimatorFault::DeviceLost(format!("{stream} stream: {err}"))
+                    }
+                    err => AnimatorFault::Stream(format!("{stream} stream: {err}")),
+                })
+            }
         };
+        let input_err = signals.clone();
+        let input_err = move |e: com::Error| input_err.fault(AnimatorFault::Player(e.to_string()));
         let stream_player = player.clone();
         let stream_config: StreamConfig = input_config.clone().into();
+        let stream_tap = tap.for_stream(&stream_config);
+        let stream_level = signals.level.clone();
 
-        let producer = Worker::<f32>::new_lifo();
-
-        let consumer = producer.stealer();
-        let output_config = StreamConfig {
-            channels: 2,
-            sample_rate: SampleRate(44100),
-            buffer_size: cpal::BufferSize::Default,
+        // Monitoring is optional, so a missing output device doesn't stop live input
+        let output = select_device(&host, Direction::Output, &devices.output)
+            .and_then(|(output, fallback)| {
+                let name = output.name()?;
+                let output_config = output.default_output_config()?;
+                let (monitor_input, monitor_output) = monitor::monitor(
+                    stream_config.sample_rate.0,
+                    stream_config.channels as usize,
+                    output_config.sample_rate().0,
+                    output_config.channels() as usize,
+                    control.clone(),
+                );
+                let stream = output::build_output_stream(
+                    &output,
+                    &output_config,
+                    monitor_output,
+                    err_fn("Output"),
+                )?;
+                log::info!("Output device: {name} ({output_config:?})");
+                Ok((
+                    Mutex::new(SendStream(stream)),
+                    name,
+                    fallback,
+                    monitor_input,
+                ))
+            })
+            .inspect_err(|e| log::warn!("Continuing without speaker output: {e}"))
+            .ok();
+        let (output, mut monitor_input) = match output {
+            Some((stream, name, fallback, monitor_input)) => {
+                (Some((stream, name, fallback)), Some(monitor_input))
+            }
+            None => (None, None),
         };
-        let s = output
-            .build_output_stream(
-                &output_config,
-                move |data: &mut [f32], _| {
-                    let sample_count = data.len() / 2;
-                    let data_to_take = sample_count * 48000 / 44100;
-                    let mut ret = Vec::with_capacity(data_to_take);
-                    for _ in 0..data_to_take {
-                        let v = match consumer.steal() {
-                            crossbeam_deque::Steal::Success(d) => d,
-                            _ => 0.0,
-                        };
-                        ret.push(v);
-                    }
-                    let idx_dist = sample_count as f32 / data_to_take as f32;
-                    for i in 0..sample_count {
-                        let idx = (i as f32 / idx_dist) as usize;
-                        data[i * 2] = ret[idx];
-                        data[i * 2 + 1] = ret[idx];
-                    }
-                },
-                err_fn,
-                None,
-            )
-            .expect("Failed to build output stream");
 
         let stream = match com_sample_type {
             SG_SampleType::SG_SAMPLE_PCM8 => input.build_input_stream(
                 &stream_config,
                 move |data: &[i8], _| {
-                    stream_player
-                        .add_input_pcm8(&mut data.to_vec())
-                        .expect("Failed to add input");
+                    stream_tap.push(data);
+                    stream_level.record(data);
+                    if let Some(monitor) = &mut monitor_input {
+                        monitor.push(data);
+                    }
+                    if let Err(e) = stream_player.add_input_pcm8(data) {
+                        input_err(e);
+                    }
                 },
-                err_fn,
+                err_fn("Input"),
                 None,
             ),
             SG_SampleType::SG_SAMPLE_PCM16 => input.build_input_stream(
                 &stream_config,
                 move |data: &[i16], _| {
-                    stream_player
-                        .add_input_pcm16(&mut data.to_vec())
-                        .expect("Failed to add input");
+                    stream_tap.push(data);
+                    stream_level.record(data);
+                    if let Some(monitor) = &mut monitor_input {
+                        monitor.push(data);
+                    }
+                    if let Err(e) = stream_player.add_input_pcm16(data) {
+                        input_err(e);
+                    }
                 },
-                err_fn,
+                err_fn("Input"),
                 None,
             ),
             SG_SampleType::SG_SAMPLE_PCM32 => input.build_input_stream(
                 &stream_config,
                 move |data: &[i32], _| {
-                    stream_player
-                        .add_input_pcm32(&mut data.to_vec())
-                        .expect("Failed to add input");
+                    stream_tap.push(data);
+                    stream_level.record(data);
+                    if let Some(monitor) = &mut monitor_input {
+                        monitor.push(data);
+                    }
+                    if let Err(e) = stream_player.add_input_pcm32(data) {
+                        input_err(e);
+                    }
                 },
-                err_fn,
+                err_fn("Input"),
                 None,
             ),
             SG_SampleType::SG_SAMPLE_FLOAT32 => input.build_input_stream(
                 &stream_config,
                 move |data: &[f32], _| {
-                    for s in data {
-                        producer.push(*s);
+                    stream_tap.push(data);
+                    stream_level.record(data);
+                    if let Some(monitor) = &mut monitor_input {
+                        monitor.push(data);
+                    }
+                    if let Err(e) = stream_player.add_input_float32(data) {
+                        input_err(e);
                     }
-                    stream_player
-                        .add_input_float32(&mut data.to_vec())
-                        .expect("Failed to add input");
                 },
-                err_fn,
+                err_fn("Input"),
                 None,
             ),
             SG_SampleType::SG_SAMPLE_FLOAT64 => input.build_input_stream(
                 &stream_config,
                 move |data: &[f64], _| {
-                    stream_player
-                        .add_input_float64(&mut data.to_vec())
-                        .expect("Failed to add input");
+                    stream_tap.push(data);
+                    stream_level.record(data);
+                    if let Some(monitor) = &mut monitor_input {
+                        monitor.push(data);
+                    }
+                    if let Err(e) = stream_player.add_input_float64(data) {
+                        input_err(e);
+                    }
                 },
-                err_fn,
+                err_fn("Input"),
                 None,
             ),
-        }
-        .expect("Failed to build input stream");
+        }?;
 
-        let ret = Self {
+        let (out_stream, output_name, output_fallback) = match output {
+            Some((stream, name, fallback)) => (Some(stream), Some(name), fallback),
+            None => (None, None, false),
+        };
+        Ok(Self {
             context: ctx,
-            names: player.processed_names(),
             player,
             stream: Mutex::new(SendStream(stream)),
-            out_stream: Mutex::new(SendStream(s)),
-            processed_data: None,
+            out_stream,
+            config: stream_config,
+            input_name,
+            output_name,
+            input_fallback,
+            output_fallback,
+        })
+    }
+
+    fn start(&self) -> Result<(), cpal::PlayStreamError> {
+        self.stream.lock().unwrap().0.play()?;
+        if let Some(out_stream) = &self.out_stream {
+            out_stream.lock().unwrap().0.play()?;
+        }
+        Ok(())
+    }
+
+    // Whether the devices in use are no longer the ones `devices` selects
+    fn is_stale(&self, devices: &AudioDevices, snapshot: &DeviceSnapshot) -> bool {
+        if snapshot.changed(
+            Direction::Input,
+            &devices.input,
+            Some(&self.input_name),
+            self.input_fallback,
+        ) {
+            log::info!("Input device changed, reopening");
+            return true;
+        }
+        if snapshot.changed(
+            Direction::Output,
+            &devices.output,
+            self.output_name.as_ref(),
+            self.output_fallback,
+        ) {
+            log::info!("Output device changed, reopening");
+            return true;
+        }
+        false
+    }
+}
+
+// The selected device, or the default with `true` if it isn't connected
+#[cfg(feature = "runtime")]
+fn select_device(
+    host: &cpal::Host,
+    direction: Direction,
+    selector: &DeviceSelector,
+) -> Result<(cpal::Device, bool), Box<dyn std::error::Error>> {
+    if let Some(device) = devices::find(host, direction, selector) {
+        return Ok((device, false));
+    }
+    if *selector != DeviceSelector::Default {
+        log::warn!("{direction:?} device {selector} not found, using the default");
+    }
+    devices::default_device(host, direction)
+        .map(|device| (device, *selector != DeviceSelector::Default))
+        .ok_or_else(|| format!("No {direction:?} device available").into())
+}
+
+impl FacialAnimator {
+    /// Starts the source on the first call, then processes `delta` worth of output.
+    pub fn update(&mut self, delta: std::time::Duration) {
+        if !self.started {
+            match &mut self.source {
+                #[cfg(feature = "runtime")]
+                Source::Live(live) => live.start(&self.signals),
+                #[cfg(feature = "runtime")]
+                Source::Clip(_) => {}
+                Source::Playback(playback) => playback.start(),
+            }
+
+            self.started = true;
+            return;
+        }
+
+        #[cfg(feature = "runtime")]
+        if let Source::Live(live) = &mut self.source {
+            if live.maintain(&self.signals) {
+                let rig = Rig::from_player(&live.input.as_ref().unwrap().player);
+                if rig != self.rig {
+                    self.rig = rig;
+                    self.retarget_generation += 1;
+                }
+            }
+        }
+
+        let output = match &mut self.source {
+            #[cfg(feature = "runtime")]
+            Source::Live(LiveSource {
+                input: Some(LiveInput { player, .. }),
+                ..
+            })
+            | Source::Clip(player) => {
+                match player.process(delta) {
+                    Ok(output) => output,
+                    Err(e) => {
+                        // Keep the last frame rather than snapping to rest
+                        self.signals.fault(AnimatorFault::Player(e.to_string()));
+                        return;
+                    }
+                }
+            }
+            // Holds the last frame until a device is back
+            #[cfg(feature = "runtime")]
+            Source::Live(_) => return,
+            Source::Playback(playback) => playback.advance(delta),
         };
+        self.processed_data = Some(output);
+
+        #[cfg(feature = "runtime")]
+        if let Some(player) = self.player() {
+            let mood = player.mood().ok();
+            let intensity = player.intensity().unwrap_or(self.intensity);
+            self.mood = mood;
+            self.intensity = intensity;
+        }
+    }
+}
 
-        ret
+pub(crate) fn process_data(mut anim: ResMut<FacialAnim>, time: Res<Time>) {
+    anim.update(time.delta());
+}
+
+pub(crate) fn process_animators(mut animators: Query<&mut FacialAnimator>, time: Res<Time>) {
+    for mut animator in &mut animators {
+        animator.update(time.delta());
     }
 }
 
-fn process_data(mut anim: ResMut<FacialAnim>, time: Res<Time>, mut started_capturing: Local<bool>) {
-    if !*started_capturing {
-        anim.stream
-            .lock()
-            .unwrap()
-            .0
-            .play()
-            .expect("Failed to begin microphone capture");
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        export,
+        track::{NodeType, RigNode, Track},
+    };
 
-        anim.out_stream
-            .lock()
+    /// A playback animator holding `values` on the `blendBoard` node's
+    /// `jawOpen` and `mouthSmile` channels.
+    pub(crate) fn animator(name: &str, values: &[f32]) -> FacialAnimator {
+        let rig = Rig {
+            nodes: vec![RigNode {
+                name: "blendBoard".to_string(),
+                node_type: NodeType::BlendShape,
+                channels: vec!["jawOpen".to_string(), "mouthSmile".to_string()],
+            }],
+        };
+        let mut track = Track::new(rig, 60.0);
+        track.push(0.0, vec![values.to_vec()]);
+        let path = std::env::temp_dir().join(format!(
+            "sg-com-animator-{}-{name}.json",
+            std::process::id()
+        ));
+        export::json::write_file(&track, &path).unwrap();
+        let animator = FacialAnimator::playback(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        animator
+    }
+
+    #[test]
+    fn processes_each_animator_from_its_own_source() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_systems(PreUpdate, process_animators);
+        let first = app.world_mut().spawn(animator("first", &[0.25, 0.5])).id();
+        let second = app.world_mut().spawn(animator("second", &[1.0, 0.0])).id();
+
+        // The first update only starts the sources
+        app.update();
+        assert!(app
+            .world()
+            .get::<FacialAnimator>(first)
             .unwrap()
-            .0
-            .play()
-            .expect("Failed to begin speaker output");
+            .processed_data
+            .is_none());
+        app.update();
+        let processed = |entity| {
+            app.world()
+                .get::<FacialAnimator>(entity)
+                .unwrap()
+                .processed_data
+                .clone()
+        };
+        assert_eq!(processed(first), Some(vec![vec![0.25, 0.5]]));
+        assert_eq!(processed(second), Some(vec![vec![1.0, 0.0]]));
 
-        *started_capturing = true;
-        return;
+        let mut animator = app.world_mut().get_mut::<FacialAnimator>(first).unwrap();
+        animator.set_retarget(RetargetMap::default());
+        assert_eq!(animator.retarget_generation(), 1);
+        assert!(animator.retarget().morphs.is_empty());
+        let second = app.world().get::<FacialAnimator>(second).unwrap();
+        assert_eq!(second.retarget_generation(), 0);
+        // Unset maps default to the pose suffix table for the rig
+        assert_eq!(second.retarget().morphs.len(), 2);
     }
-    let output = anim.player.process(time.delta()).unwrap();
-    anim.processed_data = Some(output);
 }
@@ -0,0 +1,263 @@
+use crate::{
+    audio::decode::{self, AudioFormat},
+    facial_anim::{process_animators, FacialAnimator, CLIP_SAMPLE_RATE},
+};
+use bevy::{
+    audio::{AudioSinkPlayback, PlaybackMode},
+    log,
+    prelude::*,
+    tasks::{block_on, poll_once, AsyncComputeTaskPool, Task},
+};
+use std::{io::Cursor, ops::Range, sync::Arc, time::Duration};
+
+/// Lip-syncs `AudioPlayer<AudioSource>` clips: Bevy plays the clip on the
+/// speakers while the same samples are fed to the nearest `FacialAnimator`
+/// made with `FacialAnimator::clip`, on the player's entity or an ancestor.
+/// Input follows the clip's `AudioSink`, so pausing or changing its speed
+/// keeps the face in step, and looping clips are fed again on every pass.
+/// Clips must be WAV, FLAC or Ogg Vorbis, and are decoded in the background.
+pub struct LipSyncPlugin;
+
+impl Plugin for LipSyncPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            (decode_clips, finish_decoding, feed_clips)
+                .chain()
+                .before(process_animators),
+        );
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct LipSyncClip {
+    pub animator: Entity,
+    source: AssetId<AudioSource>,
+    // Mono at `CLIP_SAMPLE_RATE`
+    samples: Vec<f32>,
+    fed: usize,
+    elapsed: Duration,
+}
+
+impl LipSyncClip {
+    pub fn is_finished(&self) -> bool {
+        self.fed >= self.samples.len()
+    }
+
+    pub fn position(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.samples.len() as f64 / CLIP_SAMPLE_RATE.to_rate() as f64)
+    }
+
+    /// Moves the clip on by `delta` of playback and returns the sample ranges
+    /// to feed, wrapping around to the start of a looping clip.
+    pub fn advance(&mut self, delta: Duration, looping: bool) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        self.elapsed += delta;
+
+        let duration = self.duration();
+        if looping && !duration.is_zero() {
+            while self.elapsed >= duration {
+                // Finish this pass, then start over like the sink does
+                ranges.push(self.fed..self.samples.len());
+                self.fed = 0;
+                self.elapsed -= duration;
+            }
+        }
+
+        let target = ((self.elapsed.as_secs_f64() * CLIP_SAMPLE_RATE.to_rate() as f64) as usize)
+            .min(self.samples.len());
+        ranges.push(self.fed..target.max(self.fed));
+        self.fed = target.max(self.fed);
+        ranges.retain(|range| !range.is_empty());
+        ranges
+    }
+}
+
+// Decoding while the clip's sink starts playing
+#[derive(Component)]
+struct DecodingClip {
+    animator: Entity,
+    source: AssetId<AudioSource>,
+    task: Task<Result<Vec<f32>, decode::DecodeError>>,
+    // Played so far, so the face catches up once decoding finishes
+    elapsed: Duration,
+}
+
+fn decode_clip(bytes: Arc<[u8]>) -> Result<Vec<f32>, decode::DecodeError> {
+    let magic: [u8; 4] = bytes
+        .get(..4)
+        .and_then(|magic| magic.try_into().ok())
+        .ok_or(decode::DecodeError::UnknownFormat)?;
+    let format = AudioFormat::from_magic(&magic).ok_or(decode::DecodeError::UnknownFormat)?;
+    let audio = decode::decode(Cursor::new(bytes), format)?;
+
+    let mono = decode::DecodedAudio {
+        samples: audio.to_mono(),
+        sample_rate: audio.sample_rate,
+        channels: 1,
+    };
+    Ok(mono.resample(CLIP_SAMPLE_RATE.to_rate() as u32).samples)
+}
+
+type ClipPlayerQuery<'a> = (
+    Entity,
+    Ref<'a, AudioPlayer<AudioSource>>,
+    Option<&'a LipSyncClip>,
+    Option<&'a DecodingClip>,
+);
+
+fn decode_clips(
+    mut commands: Commands,
+    players: Query<ClipPlayerQuery>,
+    mut removed: RemovedComponents<AudioPlayer<AudioSource>>,
+    animators: Query<(), With<FacialAnimator>>,
+    parents: Query<&Parent>,
+    sources: Res<Assets<AudioSource>>,
+) {
+    for entity in removed.read() {
+        if let Some(mut entity) = commands.get_entity(entity) {
+            entity.remove::<(LipSyncClip, DecodingClip)>();
+        }
+    }
+
+    for (entity, player, clip, decoding) in &players {
+        // Restarted clips are re-inserted, which also counts as a change
+        let source = clip
+            .map(|clip| clip.source)
+            .or(decoding.map(|decoding| decoding.source));
+        if source == Some(player.0.id()) && !player.is_changed() {
+            continue;
+        }
+
+        let Some(animator) = std::iter::once(entity)
+            .chain(parents.iter_ancestors(entity))
+            .find(|ancestor| animators.contains(*ancestor))
+        else {
+            continue;
+        };
+        // Not loaded yet; try again next frame
+        let Some(source) = sources.get(&player.0) else {
+            continue;
+        };
+
+        let bytes = source.bytes.clone();
+        let task = AsyncComputeTaskPool::get().spawn(async move { decode_clip(bytes) });
+        commands
+            .entity(entity)
+            .remove::<LipSyncClip>()
+            .insert(DecodingClip {
+                animator,
+                source: player.0.id(),
+                task,
+                elapsed: Duration::ZERO,
+            });
+    }
+}
+
+fn finish_decoding(
+    mut commands: Commands,
+    mut clips: Query<(Entity, &mut DecodingClip, Option<&AudioSink>)>,
+    time: Res<Time>,
+) {
+    for (entity, mut decoding, sink) in &mut clips {
+        if let Some(sink) = sink.filter(|sink| !sink.is_paused()) {
+            decoding.elapsed += time.delta().mul_f32(sink.speed());
+        }
+        let Some(result) = block_on(poll_once(&mut decoding.task)) else {
+            continue;
+        };
+
+        let samples = result.unwrap_or_else(|e| {
+            log::error!("Failed to decode lip-sync clip on {entity}: {e}");
+            Vec::new()
+        });
+        commands
+            .entity(entity)
+            .remove::<DecodingClip>()
+            .insert(LipSyncClip {
+                animator: decoding.animator,
+                source: decoding.source,
+                samples,
+                fed: 0,
+                elapsed: decoding.elapsed,
+            });
+    }
+}
+
+type LipSyncClipQuery<'a> = (
+    Entity,
+    &'a mut LipSyncClip,
+    Option<&'a AudioSink>,
+    Option<&'a PlaybackSettings>,
+);
+
+fn feed_clips(
+    mut clips: Query<LipSyncClipQuery>,
+    animators: Query<&FacialAnimator>,
+    time: Res<Time>,
+) {
+    for (entity, mut clip, sink, settings) in &mut clips {
+        // Bevy inserts the sink once playback has actually begun
+        let Some(sink) = sink else {
+            continue;
+        };
+        let looping = settings.is_some_and(|settings| matches!(settings.mode, PlaybackMode::Loop));
+        if sink.is_paused() || (clip.is_finished() && !looping) {
+            continue;
+        }
+        let Ok(animator) = animators.get(clip.animator) else {
+            continue;
+        };
+
+        for range in clip.advance(time.delta().mul_f32(sink.speed()), looping) {
+            if let Err(e) = animator.feed(&clip.samples[range]) {
+                log::error!("Failed to feed lip-sync clip on {entity}: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(seconds: f32) -> LipSyncClip {
+        let len = (seconds * CLIP_SAMPLE_RATE.to_rate() as f32) as usize;
+        LipSyncClip {
+            animator: Entity::PLACEHOLDER,
+            source: AssetId::default(),
+            samples: vec![0.0; len],
+            fed: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    fn advance(clip: &mut LipSyncClip, millis: u64, looping: bool) -> Vec<(usize, usize)> {
+        clip.advance(Duration::from_millis(millis), looping)
+            .into_iter()
+            .map(|range| (range.start, range.end))
+            .collect()
+    }
+
+    #[test]
+    fn stops_at_the_end_of_a_single_pass() {
+        let mut clip = clip(1.0);
+        assert_eq!(advance(&mut clip, 600, false), [(0, 9600)]);
+        assert_eq!(advance(&mut clip, 600, false), [(9600, 16000)]);
+        assert!(clip.is_finished());
+        assert!(advance(&mut clip, 600, false).is_empty());
+    }
+
+    #[test]
+    fn wraps_around_when_looping() {
+        let mut clip = clip(1.0);
+        advance(&mut clip, 600, true);
+        assert_eq!(advance(&mut clip, 600, true), [(9600, 16000), (0, 3200)]);
+        assert_eq!(clip.position(), Duration::from_millis(200));
+        assert_eq!(advance(&mut clip, 100, true), [(3200, 4800)]);
+    }
+}
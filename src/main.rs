@@ -1,12 +1,41 @@
 #![allow(dead_code)]
 
-use std::f32::consts::PI;
+use std::{sync::Mutex, time::Duration};
 
-use bevy::{prelude::*, render::mesh::morph::MeshMorphWeights};
-use facial_anim::FacialAnim;
+use bevy::{
+    app::{ScheduleRunnerPlugin, TerminalCtrlCHandlerPlugin},
+    log::LogPlugin,
+    prelude::*,
+};
+use config::ViewerConfig;
+use facial_anim::{AnimSource, FacialAnim, FacialAnimPlugin, FacialAnimator};
+use recorder::SessionRecorder;
+use retarget::{Preset, RetargetMap};
+use vrm::{VrmScene, VRM_MODEL_LABEL};
 
+mod audio;
+#[cfg(feature = "runtime")]
+mod bake;
+mod binding;
+#[cfg(feature = "runtime")]
+mod character;
+mod clip;
+#[cfg(feature = "runtime")]
 mod com;
+mod config;
+mod events;
+mod export;
 mod facial_anim;
+#[cfg(feature = "runtime")]
+mod lip_sync;
+mod output;
+mod playback;
+mod recorder;
+mod retarget;
+mod sprite_mouth;
+mod track;
+mod viseme;
+mod vrm;
 
 fn main() -> AppExit {
     // let ctx = context::initialize(CHARACTER_DATA.to_vec(), ALGORITHM_DATA.to_vec()).unwrap();
@@ -25,162 +54,552 @@ fn main() -> AppExit {
     //     thread::sleep(Duration::from_millis(10));
     // }
 
-    App::new()
-        .add_plugins(DefaultPlugins.set(AssetPlugin {
-            file_path: format!("{}/assets", env!("CARGO_MANIFEST_DIR")),
+    if has_flag("--list-devices") {
+        list_devices();
+        return AppExit::Success;
+    }
+    #[cfg(feature = "runtime")]
+    if let Some(path) = arg_value("--bake") {
+        return bake_audio(&path);
+    }
+
+    // Logging isn't set up until the app is built, so report to stderr
+    let config = match viewer_config() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            return AppExit::error();
+        }
+    };
+
+    let mut app = App::new();
+    if config.headless {
+        app.add_plugins(headless_plugins(&config));
+    } else {
+        app.add_plugins(DefaultPlugins.set(AssetPlugin {
+            file_path: config.asset_path.clone(),
             ..Default::default()
-        }))
-        .add_plugins(facial_anim::FacialAnimPlugin)
-        .insert_resource(AmbientLight {
-            brightness: 100.,
+        }));
+    }
+
+    // Nothing has run yet, so a track that fails to load exits before any window opens
+    let anim = match open_animator(&config) {
+        Ok(anim) => anim,
+        Err(e) => {
+            error!("{e}");
+            return AppExit::error();
+        }
+    };
+    app.add_plugins(FacialAnimPlugin {
+        anim: Mutex::new(Some(anim)),
+        retarget: config.retarget_map.clone(),
+        mood: config.mood.clone(),
+        intensity: config.intensity,
+        monitor: config.monitor.clone(),
+    })
+    .add_plugins(events::LipSyncEventsPlugin::default())
+    .add_plugins(recorder::SessionRecorderPlugin {
+        frame_rate: config.frame_rate as f32,
+    })
+    .add_plugins(viseme::VisemePlugin {
+        set: viseme_set(),
+        map: read_retarget_arg("--viseme-map"),
+    });
+
+    if !config.headless {
+        app.add_plugins(binding::MorphBindingPlugin)
+            .add_plugins(clip::TrackClipPlugin)
+            .add_plugins(vrm::VrmPlugin)
+            .add_plugins(sprite_mouth::SpriteMouthPlugin)
+            .insert_resource(AmbientLight {
+                brightness: config.lighting.ambient_brightness,
+                ..Default::default()
+            })
+            .add_systems(Startup, setup)
+            .add_systems(Update, (toggle_recording, toggle_monitor));
+    }
+
+    // `--record <path>` starts recording a session right away, saved on exit
+    if let Some(path) = arg_value("--record") {
+        app.world_mut()
+            .resource_mut::<SessionRecorder>()
+            .start(path);
+    }
+
+    app.insert_resource(config)
+        .add_plugins(output_plugins)
+        .add_plugins(runtime_plugins)
+        .run()
+}
+
+// `--headless` runs the audio, SG and output pipeline at `--frame-rate` updates per second,
+// without a window, renderer, scene or bevy_audio. Ctrl+C exits and saves any recording.
+fn headless_plugins(config: &ViewerConfig) -> impl PluginGroup {
+    MinimalPlugins
+        .set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+            1.0 / config.frame_rate.max(1.0),
+        )))
+        .add(LogPlugin::default())
+        .add(TerminalCtrlCHandlerPlugin)
+        // Loads `.k` characters
+        .add(AssetPlugin {
+            file_path: config.asset_path.clone(),
             ..Default::default()
         })
-        .add_systems(Startup, setup)
-        .add_systems(Update, (name_morphs, setup_animations, run_anim))
-        .run()
 }
 
-fn setup(asset_server: Res<AssetServer>, mut commands: Commands) {
-    commands.init_resource::<MorphNames>();
-    commands.spawn(SceneRoot(
-        asset_server.load(GltfAssetLabel::Scene(0).from_asset("miku.glb")),
+// `--config <file>` reads a `ViewerConfig`, `sg-com.ron` in the working directory by default.
+// `--assets`, `--model`, `--camera-position x,y,z`, `--camera-target x,y,z`, `--fov`,
+// `--ambient`, `--illuminance`, `--audio-host`, `--input-device`, `--output-device`,
+// `--monitor-delay <ms>`, `--mute-monitor`, `--character`, `--retarget`, `--mood`,
+// `--intensity`, `--headless` and `--frame-rate` override its fields. The retarget map is
+// read here too, so a missing or malformed one stops the viewer.
+fn viewer_config() -> Result<ViewerConfig, String> {
+    viewer_config_from(&std::env::args().collect::<Vec<_>>())
+}
+
+fn viewer_config_from(args: &[String]) -> Result<ViewerConfig, String> {
+    let path = find_arg(args, "--config").or_else(|| {
+        std::path::Path::new(config::DEFAULT_CONFIG)
+            .exists()
+            .then(|| config::DEFAULT_CONFIG.to_string())
+    });
+    let mut config = match path {
+        Some(path) => ViewerConfig::read_file(&path)
+            .map_err(|e| format!("Failed to read config {path}: {e}"))?,
+        None => ViewerConfig::default(),
+    };
+
+    if let Some(assets) = find_arg(args, "--assets") {
+        config.asset_path = assets;
+    }
+    if let Some(model) = find_arg(args, "--model") {
+        config.model = model;
+    }
+    if let Some(position) = parse_arg(args, "--camera-position", config::parse_vec3)? {
+        config.camera.position = position;
+    }
+    if let Some(target) = parse_arg(args, "--camera-target", config::parse_vec3)? {
+        config.camera.target = target;
+    }
+    if let Some(fov) = parse_arg(args, "--fov", |v| v.parse().ok())? {
+        config.camera.fov = fov;
+    }
+    if let Some(ambient) = parse_arg(args, "--ambient", |v| v.parse().ok())? {
+        config.lighting.ambient_brightness = ambient;
+    }
+    if let Some(illuminance) = parse_arg(args, "--illuminance", |v| v.parse().ok())? {
+        config.lighting.illuminance = illuminance;
+    }
+    config.audio_host = find_arg(args, "--audio-host").or(config.audio_host);
+    config.input_device = find_arg(args, "--input-device").or(config.input_device);
+    config.output_device = find_arg(args, "--output-device").or(config.output_device);
+    if let Some(delay) = parse_arg(args, "--monitor-delay", |v| v.parse().ok())? {
+        config.monitor.delay_ms = delay;
+    }
+    if find_flag(args, "--mute-monitor") {
+        config.monitor.muted = true;
+    }
+    config.character = find_arg(args, "--character").or(config.character);
+    config.retarget = find_arg(args, "--retarget").or(config.retarget);
+    config.mood = find_arg(args, "--mood").or(config.mood);
+    config.intensity = parse_arg(args, "--intensity", |v| v.parse().ok())?.or(config.intensity);
+    if find_flag(args, "--headless") {
+        config.headless = true;
+    }
+    if let Some(frame_rate) = parse_arg(args, "--frame-rate", |v| v.parse().ok())? {
+        config.frame_rate = frame_rate;
+    }
+    config.retarget_map = config
+        .retarget
+        .as_deref()
+        .map(read_retarget)
+        .transpose()?
+        .flatten();
+    Ok(config)
+}
+
+// Malformed values are errors rather than silently falling back to the config
+fn parse_arg<T>(
+    args: &[String],
+    name: &str,
+    parse: impl FnOnce(&str) -> Option<T>,
+) -> Result<Option<T>, String> {
+    find_arg(args, name)
+        .map(|value| parse(&value).ok_or_else(|| format!("Invalid value for {name}: {value}")))
+        .transpose()
+}
+
+// `--list-devices` prints every audio host's devices, with the indices `--input-device` accepts
+fn list_devices() {
+    for (host, inputs, outputs) in audio::devices::list_all() {
+        println!("{host} input devices:");
+        inputs.iter().for_each(|device| print!("{device}"));
+        println!("{host} output devices:");
+        outputs.iter().for_each(|device| print!("{device}"));
+    }
+}
+
+// `--bake <audio>` runs a WAV, FLAC or Ogg file through SG_Com at `--bake-rate` frames per
+// second (30 by default) and writes the track to `--out`, CSV if it ends in `.csv`, otherwise
+// JSON next to the audio. `--retarget <preset|file>` writes morph target weights in place of
+// the raw channels, and picks the morphs the glTF animation keys. `--visemes <path>` also
+// writes its visemes, see `export_visemes`, and `--gltf <path>` a glTF animation, see
+// `export_gltf`.
+#[cfg(feature = "runtime")]
+fn bake_audio(path: &str) -> AppExit {
+    let frame_rate = arg_value("--bake-rate").map_or(Ok(30.0), |v| v.parse());
+    let Ok(frame_rate) = frame_rate else {
+        eprintln!("--bake-rate must be a number");
+        return AppExit::error();
+    };
+    let out = arg_value("--out").unwrap_or_else(|| format!("{path}.track.json"));
+    let retarget = match arg_value("--retarget").map_or(Ok(None), |name| read_retarget(&name)) {
+        Ok(retarget) => retarget,
+        Err(e) => {
+            eprintln!("{e}");
+            return AppExit::error();
+        }
+    };
+
+    let track = com::context()
+        .map_err(bake::BakeError::from)
+        .and_then(|ctx| bake::bake_file(ctx, path, frame_rate));
+    let track = match track {
+        Ok(track) => track,
+        Err(e) => {
+            eprintln!("Failed to bake {path}: {e}");
+            return AppExit::error();
+        }
+    };
+
+    // Retargeted tracks hold one `morphs` channel per morph target
+    let exported = match &retarget {
+        Some(retarget) => retarget.retarget_track(&track, "morphs"),
+        None => track.clone(),
+    };
+    let written = if out.ends_with(".csv") {
+        export::csv::write_file(&exported, &out)
+    } else {
+        export::json::write_file(&exported, &out)
+    };
+    if let Err(e) = written {
+        eprintln!("Failed to write {out}: {e}");
+        return AppExit::error();
+    }
+    println!("Baked {} frames to {out}", track.frames.len());
+
+    if let Some(visemes) = arg_value("--visemes") {
+        if let Err(e) = export_visemes(&track, &visemes) {
+            eprintln!("Failed to write {visemes}: {e}");
+            return AppExit::error();
+        }
+    }
+    if let Some(gltf) = arg_value("--gltf") {
+        let retarget = retarget.unwrap_or_else(|| RetargetMap::pose_suffix(&track.rig));
+        if let Err(e) = export_gltf(&track, &retarget, path, &gltf) {
+            eprintln!("Failed to write {gltf}: {e}");
+            return AppExit::error();
+        }
+    }
+    AppExit::Success
+}
+
+// Dominant visemes as CSV (`.csv`) or JSON, in the `--viseme-set` (`oculus` or `sapi`) and
+// through the `--viseme-map` retarget
+#[cfg(feature = "runtime")]
+fn export_visemes(track: &track::Track, path: &str) -> std::io::Result<()> {
+    let map = read_retarget_arg("--viseme-map").unwrap_or_else(viseme::viseme_map);
+    let classifier = viseme::VisemeClassifier::new(viseme_set(), &map, &track.rig);
+    export::viseme::write_file(&viseme::VisemeTrack::from_track(track, &classifier), path)
+}
+
+// A `.glb` or `.gltf` animation named after the audio file, keying the morph target weights
+// of `--gltf-target` (`Face` by default). `--merge-into <model>` adds it to a copy of the
+// model, otherwise the file holds only the animation and a placeholder mesh.
+#[cfg(feature = "runtime")]
+fn export_gltf(
+    track: &track::Track,
+    retarget: &RetargetMap,
+    audio: &str,
+    path: &str,
+) -> std::io::Result<()> {
+    let target = arg_value("--gltf-target").unwrap_or_else(|| "Face".to_string());
+    let name = std::path::Path::new(audio)
+        .file_stem()
+        .map_or_else(|| "lip_sync".into(), |stem| stem.to_string_lossy());
+    match arg_value("--merge-into") {
+        Some(model) => export::gltf::write_merged(track, retarget, model, &target, &name, path),
+        None => export::gltf::write_standalone(track, retarget, &target, &name, path),
+    }
+}
+
+fn viseme_set() -> viseme::VisemeSet {
+    match arg_value("--viseme-set").as_deref() {
+        Some("sapi") => viseme::VisemeSet::Sapi,
+        _ => viseme::VisemeSet::Oculus,
+    }
+}
+
+// `--replay <track.json>` plays a recorded or baked track instead of the microphone
+#[cfg_attr(not(feature = "runtime"), allow(unused_variables))]
+fn open_animator(config: &ViewerConfig) -> Result<FacialAnimator, String> {
+    let source = match arg_value("--replay") {
+        Some(path) => AnimSource::Playback(path.into()),
+        #[cfg(feature = "runtime")]
+        None => AnimSource::Live(config.audio_devices()),
+        #[cfg(not(feature = "runtime"))]
+        None => return Err("Built without the SG_Com runtime; pass --replay <track.json>".into()),
+    };
+    FacialAnimator::from_source(&source).map_err(|e| match &source {
+        AnimSource::Playback(path) => format!("Failed to load {}: {e}", path.display()),
+        #[cfg(feature = "runtime")]
+        AnimSource::Live(_) => e.to_string(),
+    })
+}
+
+// Plugins that need SG_Com linked
+#[cfg(feature = "runtime")]
+fn runtime_plugins(app: &mut App) {
+    let config = app.world().resource::<ViewerConfig>();
+    let (character, headless) = (config.character.clone(), config.headless);
+    app.add_plugins(character::CharacterPlugin { character });
+    // Clips play through bevy_audio, which headless mode leaves out
+    if !headless {
+        app.add_plugins(lip_sync::LipSyncPlugin);
+    }
+}
+
+#[cfg(not(feature = "runtime"))]
+fn runtime_plugins(_app: &mut App) {}
+
+// `--osc <host:port>` streams every frame over OSC, `--osc-bundle` sends one bundle per frame
+// `--vmc <host:port>` sends VRM blend shapes over VMC, `--vmc-map <file.json>` overrides the table
+// `--livelink <host:port>` sends a Live Link Face subject (`--livelink-subject`, `--livelink-map`)
+// `--serve <address>` streams frames to WebSocket clients, as binary with `--serve-binary`
+fn output_plugins(app: &mut App) {
+    if let Some(target) = arg_value("--osc") {
+        app.add_plugins(output::osc::OscOutputPlugin {
+            target,
+            bundle: has_flag("--osc-bundle"),
+        });
+    }
+
+    if let Some(target) = arg_value("--vmc") {
+        app.add_plugins(output::vmc::VmcOutputPlugin {
+            target,
+            retarget: read_retarget_arg("--vmc-map"),
+        });
+    }
+
+    if let Some(target) = arg_value("--livelink") {
+        app.add_plugins(output::livelink::LiveLinkOutputPlugin {
+            target,
+            subject: arg_value("--livelink-subject").unwrap_or_else(|| "sg-com".to_string()),
+            retarget: read_retarget_arg("--livelink-map"),
+        });
+    }
+
+    if let Some(address) = arg_value("--serve") {
+        app.add_plugins(output::websocket::WebSocketServerPlugin {
+            address,
+            format: if has_flag("--serve-binary") {
+                output::websocket::FrameFormat::Binary
+            } else {
+                output::websocket::FrameFormat::Json
+            },
+        });
+    }
+}
+
+fn read_retarget_arg(name: &str) -> Option<RetargetMap> {
+    read_retarget_or_log(&arg_value(name)?)
+}
+
+fn read_retarget_or_log(path: &str) -> Option<RetargetMap> {
+    read_retarget(path).unwrap_or_else(|e| {
+        error!("{e}");
+        None
+    })
+}
+
+// Either a preset name (`pose`, `arkit`, `vrm`) or a retarget file. `pose` is the
+// default, built once the rig is known, so it reads as `None`.
+fn read_retarget(path: &str) -> Result<Option<RetargetMap>, String> {
+    match Preset::from_name(path) {
+        Some(Preset::PoseSuffix) => Ok(None),
+        Some(preset) => Ok(Some(preset.map(&Default::default()))),
+        None => RetargetMap::read_file(path)
+            .map(Some)
+            .map_err(|e| format!("Failed to read retarget table {path}: {e}")),
+    }
+}
+
+fn arg_value(name: &str) -> Option<String> {
+    find_arg(std::env::args(), name)
+}
+
+fn has_flag(name: &str) -> bool {
+    find_flag(std::env::args(), name)
+}
+
+fn find_arg<S: AsRef<str>>(args: impl IntoIterator<Item = S>, name: &str) -> Option<String> {
+    let mut args = args.into_iter().skip_while(|arg| arg.as_ref() != name);
+    args.nth(1).map(|value| value.as_ref().to_string())
+}
+
+fn find_flag<S: AsRef<str>>(args: impl IntoIterator<Item = S>, name: &str) -> bool {
+    args.into_iter().any(|arg| arg.as_ref() == name)
+}
+
+fn setup(asset_server: Res<AssetServer>, config: Res<ViewerConfig>, mut commands: Commands) {
+    let model = config.model.clone();
+    // The scene is requested first, so a .vrm without VRM data still loads
+    let mut scene = commands.spawn((
+        SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset(model.clone()))),
+        Transform::default(),
     ));
+    if model.ends_with(".vrm") {
+        scene.insert(VrmScene {
+            model: asset_server.load(format!("{model}#{VRM_MODEL_LABEL}")),
+            retarget: config.retarget_map.clone(),
+        });
+    }
     commands.spawn((
         DirectionalLight {
-            illuminance: 500.0,
+            illuminance: config.lighting.illuminance,
             ..Default::default()
         },
-        Transform::from_rotation(Quat::from_rotation_z(PI / 2.0)),
+        Transform::from_rotation(config.lighting.rotation()),
     ));
     commands.spawn((
         Camera3d::default(),
-        Transform::from_xyz(0.0, 1.5, 0.5).looking_at(Vec3::new(0.0, 1.5, 0.0), Vec3::Y),
+        Projection::from(PerspectiveProjection {
+            fov: config.camera.fov.to_radians(),
+            ..Default::default()
+        }),
+        config.camera.transform(),
     ));
 }
 
-fn setup_animations(
-    mut has_setup: Local<bool>,
-    mut commands: Commands,
-    mut players: Query<(Entity, &Name, &Mesh3d, &Parent)>,
-    meshes: Res<Assets<Mesh>>,
-) {
-    if *has_setup {
+fn toggle_monitor(keys: Res<ButtonInput<KeyCode>>, anim: Res<FacialAnim>) {
+    if !keys.just_pressed(KeyCode::KeyM) {
         return;
     }
 
-    for (entity, name, mesh3d, parent) in &mut players {
-        if let Some(mesh) = meshes.get(mesh3d) {
-            if !mesh.has_morph_targets() {
-                continue;
-            }
-
-            info!("Name: {name}");
-            let weights = vec![0.0; mesh.morph_target_names().unwrap().len()];
-            commands.entity(parent.get()).insert_if_new(
-                MorphWeights::new(weights.clone(), Some(mesh3d.0.clone_weak())).unwrap(),
-            );
+    if let Some(monitor) = anim.monitor() {
+        let muted = monitor.toggle_muted();
+        info!(
+            "Speaker monitor {}",
+            if muted { "muted" } else { "unmuted" }
+        );
+    }
+}
 
-            commands
-                .entity(entity)
-                .insert(MeshMorphWeights::new(weights).unwrap());
+fn toggle_recording(keys: Res<ButtonInput<KeyCode>>, mut recorder: ResMut<SessionRecorder>) {
+    if !keys.just_pressed(KeyCode::KeyR) {
+        return;
+    }
 
-            *has_setup = true;
-        }
+    if recorder.is_recording() {
+        recorder.stop();
+    } else {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        recorder.start(format!("recordings/session-{timestamp}"));
     }
 }
 
-#[derive(Resource, Default)]
-pub struct MorphNames(Vec<String>);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
 
-fn name_morphs(
-    mut has_printed: Local<bool>,
-    morph_data: Query<&MorphWeights>,
-    mut morph_names: ResMut<MorphNames>,
-    meshes: Res<Assets<Mesh>>,
-) {
-    if *has_printed {
-        return;
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sg-com-main-{}-{name}", std::process::id()))
     }
 
-    let Some(morph_data) = morph_data.iter().next() else {
-        return;
-    };
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
 
-    let Some(mesh) = morph_data.first_mesh() else {
-        return;
-    };
+    #[test]
+    fn finds_args_and_flags() {
+        let args = args(&["sg-com", "--model", "a.glb", "--headless", "--fov"]);
+        assert_eq!(find_arg(&args, "--model").as_deref(), Some("a.glb"));
+        assert_eq!(find_arg(&args, "--fov"), None);
+        assert_eq!(find_arg(&args, "--mood"), None);
+        assert!(find_flag(&args, "--headless"));
+        assert!(!find_flag(&args, "--mute-monitor"));
+    }
 
-    let Some(mesh) = meshes.get(mesh) else {
-        return;
-    };
-    let Some(names) = mesh.morph_target_names() else {
-        return;
-    };
+    #[test]
+    fn overrides_the_config_file_from_the_command_line() {
+        let path = temp_path("viewer.ron");
+        std::fs::write(
+            &path,
+            r#"(model: "file.glb", mood: Some("happy"), camera: (fov: 30.0), frame_rate: 30.0)"#,
+        )
+        .unwrap();
+        let config_arg = path.to_str().unwrap();
 
-    info!("Target names:");
-    for name in names {
-        info!("  {name}");
-    }
-    morph_names.0 = names.to_vec();
-    *has_printed = true;
-}
+        let config = viewer_config_from(&args(&["sg-com", "--config", config_arg])).unwrap();
+        assert_eq!(config.model, "file.glb");
+        assert_eq!(config.mood.as_deref(), Some("happy"));
+        assert_eq!(config.camera.fov, 30.0);
 
-fn run_anim(
-    mut morph_data: Query<&mut MorphWeights>,
-    anim: Res<FacialAnim>,
-    names: Res<MorphNames>,
-    mut morph_indices: Local<Option<Vec<Option<(usize, usize)>>>>,
-) {
-    let Some(mut morph_data) = morph_data.iter_mut().next() else {
-        return;
-    };
+        let config = viewer_config_from(&args(&[
+            "sg-com",
+            "--config",
+            config_arg,
+            "--model",
+            "arg.vrm",
+            "--fov",
+            "60",
+            "--camera-position",
+            "1,2,3",
+            "--mute-monitor",
+            "--headless",
+        ]))
+        .unwrap();
+        assert_eq!(config.model, "arg.vrm");
+        assert_eq!(config.camera.fov, 60.0);
+        assert_eq!(config.camera.position, [1.0, 2.0, 3.0]);
+        assert!(config.monitor.muted);
+        assert!(config.headless);
+        // Fields without an override keep the file's values
+        assert_eq!(config.mood.as_deref(), Some("happy"));
+        assert_eq!(config.frame_rate, 30.0);
 
-    let Some(processed_data) = &anim.processed_data else {
-        return;
-    };
+        let error = viewer_config_from(&args(&["sg-com", "--config", config_arg, "--fov", "wide"]));
+        assert_eq!(error, Err("Invalid value for --fov: wide".to_string()));
+        std::fs::remove_file(path).unwrap();
 
-    let weights = morph_data.weights_mut();
-
-    if morph_indices.is_none() {
-        let name_paths = names.0.iter().map(|name| ("blendBoard", name));
-        *morph_indices = Some(
-            name_paths
-                .map(|(node_name, channel_name)| {
-                    (
-                        (node_name, channel_name),
-                        anim.names
-                            .iter()
-                            .enumerate()
-                            .find(|(_, (anim_node_name, _))| anim_node_name == node_name)
-                            .map(|(node_index, (_, node_channels))| {
-                                (
-                                    node_index,
-                                    node_channels.iter().position(|anim_channel_name| {
-                                        format!("{}_pose", anim_channel_name) == *channel_name
-                                    }),
-                                )
-                            }),
-                    )
-                })
-                .map(|((node_name, channel_name), result)| {
-                    if let Some((node_index, Some(channel_index))) = result {
-                        Some((node_index, channel_index))
-                    } else {
-                        warn!("Could not find morph target: {node_name} {channel_name}");
-                        None
-                    }
-                })
-                .collect(),
-        );
+        let missing = temp_path("missing.ron");
+        let error = viewer_config_from(&args(&["sg-com", "--config", missing.to_str().unwrap()]));
+        assert!(error.unwrap_err().starts_with("Failed to read config"));
     }
 
-    for (morph_index, (node_index, channel_index)) in morph_indices
-        .as_ref()
-        .unwrap()
-        .iter()
-        .enumerate()
-        .filter_map(|(i, x)| x.map(|y| (i, y)))
-    {
-        weights[morph_index] = processed_data[node_index][channel_index];
+    #[test]
+    fn reads_the_retarget_map_once() {
+        let config = viewer_config_from(&args(&["sg-com", "--retarget", "arkit"])).unwrap();
+        let arkit = Preset::from_name("arkit").unwrap().map(&Default::default());
+        assert_eq!(config.retarget_map, Some(arkit));
+
+        // The default map is built once the rig is known
+        let config = viewer_config_from(&args(&["sg-com", "--retarget", "pose"])).unwrap();
+        assert_eq!(config.retarget_map, None);
+        assert_eq!(
+            viewer_config_from(&args(&["sg-com"])).unwrap().retarget_map,
+            None
+        );
+
+        let missing = temp_path("missing-retarget.ron");
+        let error = viewer_config_from(&args(&["sg-com", "--retarget", missing.to_str().unwrap()]));
+        assert!(error
+            .unwrap_err()
+            .starts_with("Failed to read retarget table"));
     }
 }
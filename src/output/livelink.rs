@@ -0,0 +1,335 @@
+use crate::{
+    facial_anim::{process_data, FacialAnim},
+    output,
+    retarget::{arkit::ARKIT_BLENDSHAPES, MorphBinding, RetargetMap},
+    track::Rig,
+};
+use bevy::{log, prelude::*};
+use std::{io, net::UdpSocket, time::Duration};
+
+pub const LIVE_LINK_VERSION: u8 = 6;
+pub const LIVE_LINK_FPS: i32 = 60;
+
+// Head and eye rotations that follow the ARKit blend shapes; always sent as zero
+const LIVE_LINK_ROTATIONS: usize = 9;
+
+/// Sends frames to Unreal as a Live Link Face (ARKit) subject.
+pub struct LiveLinkOutputPlugin {
+    /// `host:port` of the Live Link source, usually port 11111
+    pub target: String,
+    pub subject: String,
+    /// Maps SG channels to ARKit names, `RetargetMap::arkit` by default
+    pub retarget: Option<RetargetMap>,
+}
+
+impl Plugin for LiveLinkOutputPlugin {
+    fn build(&self, app: &mut App) {
+        let retarget = self.retarget.clone().unwrap_or_else(RetargetMap::arkit);
+        match LiveLinkSender::connect(&self.target, &self.subject, retarget) {
+            Ok(sender) => {
+                log::info!(
+                    "Sending Live Link subject {} to {}",
+                    self.subject,
+                    self.target
+                );
+                app.insert_resource(sender);
+                app.add_systems(PreUpdate, send_live_link.after(process_data));
+            }
+            Err(e) => log::error!("Failed to open Live Link output to {}: {e}", self.target),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameTime {
+    pub frame: i32,
+    pub subframe: f32,
+    pub numerator: i32,
+    pub denominator: i32,
+}
+
+impl FrameTime {
+    pub fn from_elapsed(elapsed: Duration) -> Self {
+        let frames = elapsed.as_secs_f64() * LIVE_LINK_FPS as f64;
+        Self {
+            frame: frames as i32,
+            subframe: frames.fract() as f32,
+            numerator: LIVE_LINK_FPS,
+            denominator: 1,
+        }
+    }
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+/// Encodes a packet with `curves` in `ARKIT_BLENDSHAPES` order.
+pub fn encode_packet(
+    buf: &mut Vec<u8>,
+    device_id: &str,
+    subject: &str,
+    time: FrameTime,
+    curves: &[f32],
+) {
+    buf.push(LIVE_LINK_VERSION);
+    write_string(buf, device_id);
+    write_string(buf, subject);
+
+    buf.extend_from_slice(&time.frame.to_be_bytes());
+    buf.extend_from_slice(&time.subframe.to_be_bytes());
+    buf.extend_from_slice(&time.numerator.to_be_bytes());
+    buf.extend_from_slice(&time.denominator.to_be_bytes());
+
+    buf.push((ARKIT_BLENDSHAPES.len() + LIVE_LINK_ROTATIONS) as u8);
+    for index in 0..ARKIT_BLENDSHAPES.len() + LIVE_LINK_ROTATIONS {
+        let value = curves.get(index).copied().unwrap_or_default();
+        buf.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+#[derive(Resource)]
+pub struct LiveLinkSender {
+    socket: UdpSocket,
+    subject: String,
+    device_id: String,
+    retarget: RetargetMap,
+    // The rig `binding` was built for
+    rig: Rig,
+    binding: Option<MorphBinding>,
+    buf: Vec<u8>,
+}
+
+impl LiveLinkSender {
+    pub fn connect(target: &str, subject: &str, retarget: RetargetMap) -> io::Result<Self> {
+        Ok(Self {
+            socket: output::connect_udp(target)?,
+            subject: subject.to_string(),
+            device_id: format!("sg-com-{}", std::process::id()),
+            retarget,
+            rig: Rig::default(),
+            binding: None,
+            buf: Vec::new(),
+        })
+    }
+
+    pub fn send_frame(
+        &mut self,
+        rig: &Rig,
+        values: &[Vec<f32>],
+        elapsed: Duration,
+    ) -> io::Result<()> {
+        if self.rig != *rig {
+            // The character was swapped; bind its channels again
+            self.rig = rig.clone();
+            self.binding = None;
+        }
+        let binding = self.binding.get_or_insert_with(|| {
+            let names = ARKIT_BLENDSHAPES.map(String::from);
+            let binding = self.retarget.bind(rig, &names);
+            for (index, name) in names.iter().enumerate() {
+                if !binding.is_bound(index) {
+                    log::debug!("No SG channel found for ARKit curve {name}");
+                }
+            }
+            if !(0..names.len()).any(|index| binding.is_bound(index)) {
+                log::warn!(
+                    "No SG channel maps to an ARKit curve; pass --livelink-map to map this character"
+                );
+            }
+            binding
+        });
+
+        self.buf.clear();
+        encode_packet(
+            &mut self.buf,
+            &self.device_id,
+            &self.subject,
+            FrameTime::from_elapsed(elapsed),
+            &binding.evaluate(values),
+        );
+        self.socket.send(&self.buf)?;
+        Ok(())
+    }
+}
+
+fn send_live_link(
+    mut sender: ResMut<LiveLinkSender>,
+    anim: Res<FacialAnim>,
+    time: Res<Time>,
+    mut failed: Local<bool>,
+) {
+    let Some(processed_data) = &anim.processed_data else {
+        return;
+    };
+
+    match sender.send_frame(&anim.rig, processed_data, time.elapsed()) {
+        Ok(()) => *failed = false,
+        Err(e) if !*failed => {
+            log::warn!("Failed to send Live Link frame: {e}");
+            *failed = true;
+        }
+        Err(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        retarget::MorphMapping,
+        track::{NodeType, RigNode},
+    };
+
+    struct Packet {
+        version: u8,
+        device_id: String,
+        subject: String,
+        time: FrameTime,
+        curves: Vec<f32>,
+    }
+
+    struct Reader<'a>(&'a [u8]);
+
+    impl Reader<'_> {
+        fn take(&mut self, len: usize) -> &[u8] {
+            let (bytes, rest) = self.0.split_at(len);
+            self.0 = rest;
+            bytes
+        }
+
+        fn u8(&mut self) -> u8 {
+            self.take(1)[0]
+        }
+
+        fn u32(&mut self) -> u32 {
+            u32::from_be_bytes(self.take(4).try_into().unwrap())
+        }
+
+        fn string(&mut self) -> String {
+            let len = self.u32() as usize;
+            String::from_utf8(self.take(len).to_vec()).unwrap()
+        }
+    }
+
+    fn decode_packet(data: &[u8]) -> Packet {
+        let mut reader = Reader(data);
+        let version = reader.u8();
+        let device_id = reader.string();
+        let subject = reader.string();
+        let time = FrameTime {
+            frame: reader.u32() as i32,
+            subframe: f32::from_bits(reader.u32()),
+            numerator: reader.u32() as i32,
+            denominator: reader.u32() as i32,
+        };
+        let count = reader.u8() as usize;
+        let curves = (0..count).map(|_| f32::from_bits(reader.u32())).collect();
+        assert!(reader.0.is_empty());
+
+        Packet {
+            version,
+            device_id,
+            subject,
+            time,
+            curves,
+        }
+    }
+
+    #[test]
+    fn sends_decodable_packets() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+
+        let rig = Rig {
+            nodes: vec![RigNode {
+                name: "blendBoard".to_string(),
+                node_type: NodeType::Control,
+                channels: vec!["mouth_open".to_string(), "smile_l".to_string()],
+            }],
+        };
+        let retarget = RetargetMap {
+            morphs: vec![
+                MorphMapping::new("blendBoard", "mouth_open", "jawOpen"),
+                MorphMapping::new("blendBoard", "smile_l", "mouthSmileLeft"),
+            ],
+            ..Default::default()
+        };
+        let mut sender = LiveLinkSender::connect(&target, "face", retarget).unwrap();
+        sender
+            .send_frame(&rig, &[vec![0.75, 0.5]], Duration::from_millis(1525))
+            .unwrap();
+
+        let mut buf = [0; 1024];
+        let len = listener.recv(&mut buf).unwrap();
+        let packet = decode_packet(&buf[..len]);
+
+        assert_eq!(packet.version, 6);
+        assert!(packet.device_id.starts_with("sg-com-"));
+        assert_eq!(packet.subject, "face");
+        assert_eq!(packet.time.frame, 91);
+        assert!((packet.time.subframe - 0.5).abs() < 1e-3);
+        assert_eq!((packet.time.numerator, packet.time.denominator), (60, 1));
+        assert_eq!(packet.curves.len(), 61);
+
+        let index = |name| ARKIT_BLENDSHAPES.iter().position(|n| *n == name).unwrap();
+        assert_eq!(packet.curves[index("jawOpen")], 0.75);
+        assert_eq!(packet.curves[index("mouthSmileLeft")], 0.5);
+        assert_eq!(
+            packet.curves.iter().filter(|value| **value != 0.0).count(),
+            2
+        );
+    }
+
+    #[test]
+    fn rebinds_when_the_rig_changes() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+
+        let rig = |channels: &[&str]| Rig {
+            nodes: vec![RigNode {
+                name: "blendBoard".to_string(),
+                node_type: NodeType::Control,
+                channels: channels.iter().map(|channel| channel.to_string()).collect(),
+            }],
+        };
+        let retarget = RetargetMap {
+            morphs: vec![MorphMapping::new("blendBoard", "mouth_open", "jawOpen")],
+            ..Default::default()
+        };
+        let mut sender = LiveLinkSender::connect(&target, "face", retarget).unwrap();
+        let jaw_open = ARKIT_BLENDSHAPES
+            .iter()
+            .position(|name| *name == "jawOpen")
+            .unwrap();
+        let mut buf = [0; 1024];
+
+        sender
+            .send_frame(
+                &rig(&["mouth_open", "smile_l"]),
+                &[vec![0.75, 0.5]],
+                Duration::ZERO,
+            )
+            .unwrap();
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(decode_packet(&buf[..len]).curves[jaw_open], 0.75);
+
+        // A swapped character can put the same channel somewhere else
+        sender
+            .send_frame(
+                &rig(&["smile_l", "mouth_open"]),
+                &[vec![0.5, 0.25]],
+                Duration::ZERO,
+            )
+            .unwrap();
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(decode_packet(&buf[..len]).curves[jaw_open], 0.25);
+    }
+}
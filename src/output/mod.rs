@@ -0,0 +1,24 @@
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+pub mod livelink;
+pub mod osc;
+pub mod vmc;
+pub mod websocket;
+
+/// Binds a local socket matching the address family of `target` and connects it.
+pub fn connect_udp(target: &str) -> io::Result<UdpSocket> {
+    let addr = target
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Could not resolve address"))?;
+    let local: SocketAddr = match addr {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(addr)?;
+    Ok(socket)
+}
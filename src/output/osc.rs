@@ -0,0 +1,338 @@
+use crate::{
+    facial_anim::{process_data, FacialAnim},
+    output,
+    track::Rig,
+};
+use bevy::{log, prelude::*};
+use std::{io, net::UdpSocket};
+
+pub const OSC_PREFIX: &str = "/sg";
+
+/// Sends every processed frame as `/sg/<node>/<channel> f` messages.
+pub struct OscOutputPlugin {
+    /// `host:port` of the receiver
+    pub target: String,
+    /// Pack each frame into a single bundle instead of one packet per channel
+    pub bundle: bool,
+}
+
+impl Plugin for OscOutputPlugin {
+    fn build(&self, app: &mut App) {
+        match OscSender::connect(&self.target, self.bundle) {
+            Ok(sender) => {
+                log::info!("Sending OSC to {}", self.target);
+                app.insert_resource(sender);
+                app.add_systems(PreUpdate, send_osc.after(process_data));
+            }
+            Err(e) => log::error!("Failed to open OSC output to {}: {e}", self.target),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    String(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    pub fn new(address: impl Into<String>, args: Vec<OscArg>) -> Self {
+        Self {
+            address: address.into(),
+            args,
+        }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        write_string(buf, &self.address);
+
+        let mut tags = String::from(",");
+        for arg in &self.args {
+            tags.push(match arg {
+                OscArg::Int(_) => 'i',
+                OscArg::Float(_) => 'f',
+                OscArg::String(_) => 's',
+            });
+        }
+        write_string(buf, &tags);
+
+        for arg in &self.args {
+            match arg {
+                OscArg::Int(value) => buf.extend_from_slice(&value.to_be_bytes()),
+                OscArg::Float(value) => buf.extend_from_slice(&value.to_be_bytes()),
+                OscArg::String(value) => write_string(buf, value),
+            }
+        }
+    }
+}
+
+// Null terminated and padded to a multiple of 4 bytes
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(value.as_bytes());
+    let padding = 4 - value.len() % 4;
+    buf.resize(buf.len() + padding, 0);
+}
+
+/// Encodes `messages` as a bundle to be applied immediately.
+pub fn encode_bundle(messages: &[OscMessage], buf: &mut Vec<u8>) {
+    write_string(buf, "#bundle");
+    // Time tag 1 means "immediately"
+    buf.extend_from_slice(&1u64.to_be_bytes());
+
+    for message in messages {
+        let start = buf.len();
+        buf.extend_from_slice(&[0; 4]);
+        message.encode(buf);
+        let size = (buf.len() - start - 4) as i32;
+        buf[start..start + 4].copy_from_slice(&size.to_be_bytes());
+    }
+}
+
+/// Replaces characters OSC reserves for address patterns.
+pub fn address_part(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            ' ' | '#' | '*' | ',' | '/' | '?' | '[' | ']' | '{' | '}' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+pub fn channel_addresses(rig: &Rig) -> Vec<String> {
+    rig.channel_paths()
+        .map(|(node, channel)| {
+            format!(
+                "{OSC_PREFIX}/{}/{}",
+                address_part(node),
+                address_part(channel)
+            )
+        })
+        .collect()
+}
+
+#[derive(Resource)]
+pub struct OscSender {
+    socket: UdpSocket,
+    bundle: bool,
+    // The rig `addresses` were built for
+    rig: Rig,
+    addresses: Vec<String>,
+    buf: Vec<u8>,
+}
+
+impl OscSender {
+    pub fn connect(target: &str, bundle: bool) -> io::Result<Self> {
+        Ok(Self {
+            socket: output::connect_udp(target)?,
+            bundle,
+            rig: Rig::default(),
+            addresses: Vec::new(),
+            buf: Vec::new(),
+        })
+    }
+
+    /// Nodes SG_Com returned no data for are skipped.
+    pub fn send_frame(&mut self, rig: &Rig, values: &[Vec<f32>]) -> io::Result<()> {
+        if self.rig != *rig {
+            self.rig = rig.clone();
+            self.addresses = channel_addresses(rig);
+        }
+
+        let messages: Vec<_> = self
+            .addresses
+            .iter()
+            .zip(rig.aligned_values(values))
+            .filter_map(|(address, value)| {
+                Some(OscMessage::new(
+                    address.clone(),
+                    vec![OscArg::Float(value?)],
+                ))
+            })
+            .collect();
+
+        if self.bundle {
+            self.send(|buf| encode_bundle(&messages, buf))
+        } else {
+            messages
+                .iter()
+                .try_for_each(|message| self.send(|buf| message.encode(buf)))
+        }
+    }
+
+    fn send(&mut self, encode: impl FnOnce(&mut Vec<u8>)) -> io::Result<()> {
+        self.buf.clear();
+        encode(&mut self.buf);
+        self.socket.send(&self.buf)?;
+        Ok(())
+    }
+}
+
+fn send_osc(mut sender: ResMut<OscSender>, anim: Res<FacialAnim>, mut failed: Local<bool>) {
+    let Some(processed_data) = &anim.processed_data else {
+        return;
+    };
+
+    match sender.send_frame(&anim.rig, processed_data) {
+        Ok(()) => *failed = false,
+        // Nothing listening yet; only report it once until it recovers
+        Err(e) if !*failed => {
+            log::warn!("Failed to send OSC frame: {e}");
+            *failed = true;
+        }
+        Err(_) => {}
+    }
+}
+
+// The decoders and listener are shared with the VMC tests
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::track::{NodeType, RigNode};
+    use std::time::Duration;
+
+    fn read_string(data: &[u8], offset: &mut usize) -> String {
+        let end = *offset + data[*offset..].iter().position(|b| *b == 0).unwrap();
+        let value = String::from_utf8(data[*offset..end].to_vec()).unwrap();
+        *offset = (end / 4 + 1) * 4;
+        value
+    }
+
+    fn read_u32(data: &[u8], offset: &mut usize) -> u32 {
+        let value = u32::from_be_bytes(data[*offset..*offset + 4].try_into().unwrap());
+        *offset += 4;
+        value
+    }
+
+    fn decode_message(data: &[u8]) -> OscMessage {
+        let mut offset = 0;
+        let address = read_string(data, &mut offset);
+        let tags = read_string(data, &mut offset);
+        let args = tags[1..]
+            .chars()
+            .map(|tag| match tag {
+                'i' => OscArg::Int(read_u32(data, &mut offset) as i32),
+                'f' => OscArg::Float(f32::from_bits(read_u32(data, &mut offset))),
+                's' => OscArg::String(read_string(data, &mut offset)),
+                tag => panic!("unexpected tag {tag}"),
+            })
+            .collect();
+        assert_eq!(offset, data.len());
+        OscMessage { address, args }
+    }
+
+    pub(crate) fn decode_bundle(data: &[u8]) -> Vec<OscMessage> {
+        let mut offset = 0;
+        assert_eq!(read_string(data, &mut offset), "#bundle");
+        assert_eq!(read_u32(data, &mut offset), 0);
+        assert_eq!(read_u32(data, &mut offset), 1);
+
+        let mut messages = Vec::new();
+        while offset < data.len() {
+            let size = read_u32(data, &mut offset) as usize;
+            messages.push(decode_message(&data[offset..offset + size]));
+            offset += size;
+        }
+        messages
+    }
+
+    fn rig() -> Rig {
+        let node = |name: &str, channels: &[&str]| RigNode {
+            name: name.to_string(),
+            node_type: NodeType::Control,
+            channels: channels.iter().map(|channel| channel.to_string()).collect(),
+        };
+        Rig {
+            nodes: vec![
+                node("jaw", &["open"]),
+                node("tongue", &["out"]),
+                node("mouth corner", &["smile"]),
+            ],
+        }
+    }
+
+    pub(crate) fn listener() -> (UdpSocket, String) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let target = socket.local_addr().unwrap().to_string();
+        (socket, target)
+    }
+
+    pub(crate) fn receive(socket: &UdpSocket) -> Vec<u8> {
+        let mut buf = [0; 2048];
+        let len = socket.recv(&mut buf).unwrap();
+        buf[..len].to_vec()
+    }
+
+    fn float(address: &str, value: f32) -> OscMessage {
+        OscMessage::new(address, vec![OscArg::Float(value)])
+    }
+
+    #[test]
+    fn encodes_padded_strings() {
+        let mut buf = Vec::new();
+        OscMessage::new("/abc", vec![OscArg::Int(7), OscArg::String("hi".into())]).encode(&mut buf);
+        assert_eq!(buf, b"/abc\0\0\0\0,is\0\0\0\0\x07hi\0\0");
+    }
+
+    #[test]
+    fn sends_messages_per_channel_skipping_missing_nodes() {
+        let (socket, target) = listener();
+        let mut sender = OscSender::connect(&target, false).unwrap();
+        sender
+            .send_frame(&rig(), &[vec![0.5], Vec::new(), vec![0.25]])
+            .unwrap();
+
+        assert_eq!(
+            decode_message(&receive(&socket)),
+            float("/sg/jaw/open", 0.5)
+        );
+        assert_eq!(
+            decode_message(&receive(&socket)),
+            float("/sg/mouth_corner/smile", 0.25)
+        );
+    }
+
+    #[test]
+    fn sends_bundles() {
+        let (socket, target) = listener();
+        let mut sender = OscSender::connect(&target, true).unwrap();
+        sender
+            .send_frame(&rig(), &[vec![0.5], vec![1.0], vec![0.25]])
+            .unwrap();
+
+        assert_eq!(
+            decode_bundle(&receive(&socket)),
+            [
+                float("/sg/jaw/open", 0.5),
+                float("/sg/tongue/out", 1.0),
+                float("/sg/mouth_corner/smile", 0.25),
+            ]
+        );
+    }
+
+    #[test]
+    fn rebuilds_addresses_for_a_new_rig() {
+        let (socket, target) = listener();
+        let mut sender = OscSender::connect(&target, true).unwrap();
+        sender
+            .send_frame(&rig(), &[vec![0.5], vec![1.0], vec![0.25]])
+            .unwrap();
+        receive(&socket);
+
+        let mut renamed = rig();
+        renamed.nodes[0].name = "lips".to_string();
+        sender
+            .send_frame(&renamed, &[vec![0.5], vec![1.0], vec![0.25]])
+            .unwrap();
+        assert_eq!(decode_bundle(&receive(&socket))[0].address, "/sg/lips/open");
+    }
+}
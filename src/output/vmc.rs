@@ -0,0 +1,183 @@
+use crate::{
+    facial_anim::{process_data, FacialAnim},
+    output::{
+        self,
+        osc::{encode_bundle, OscArg, OscMessage},
+    },
+    retarget::{MorphBinding, RetargetMap},
+    track::Rig,
+};
+use bevy::{log, prelude::*};
+use std::{io, net::UdpSocket};
+
+pub const VMC_BLEND_VAL: &str = "/VMC/Ext/Blend/Val";
+pub const VMC_BLEND_APPLY: &str = "/VMC/Ext/Blend/Apply";
+
+/// Sends VRM blend shape values to a Virtual Motion Capture receiver.
+pub struct VmcOutputPlugin {
+    /// `host:port` of the receiver, usually port 39539
+    pub target: String,
+    /// Maps SG channels to VRM blend shape names, `RetargetMap::vrm` by default
+    pub retarget: Option<RetargetMap>,
+}
+
+impl Plugin for VmcOutputPlugin {
+    fn build(&self, app: &mut App) {
+        let retarget = self.retarget.clone().unwrap_or_else(RetargetMap::vrm);
+        match VmcSender::connect(&self.target, retarget) {
+            Ok(sender) => {
+                log::info!("Sending VMC to {}", self.target);
+                app.insert_resource(sender);
+                app.add_systems(PreUpdate, send_vmc.after(process_data));
+            }
+            Err(e) => log::error!("Failed to open VMC output to {}: {e}", self.target),
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct VmcSender {
+    socket: UdpSocket,
+    retarget: RetargetMap,
+    names: Vec<String>,
+    // The rig `binding` was built for
+    rig: Rig,
+    binding: Option<MorphBinding>,
+    buf: Vec<u8>,
+}
+
+impl VmcSender {
+    pub fn connect(target: &str, retarget: RetargetMap) -> io::Result<Self> {
+        Ok(Self {
+            socket: output::connect_udp(target)?,
+            names: retarget.morph_names(),
+            retarget,
+            rig: Rig::default(),
+            binding: None,
+            buf: Vec::new(),
+        })
+    }
+
+    /// Sends one `Val` per blend shape followed by `Apply`, as a single bundle.
+    pub fn send_frame(&mut self, rig: &Rig, values: &[Vec<f32>]) -> io::Result<()> {
+        if self.rig != *rig {
+            // The character was swapped; bind its channels again
+            self.rig = rig.clone();
+            self.binding = None;
+        }
+        let binding = self.binding.get_or_insert_with(|| {
+            let binding = self.retarget.bind(rig, &self.names);
+            for (index, name) in self.names.iter().enumerate() {
+                if !binding.is_bound(index) {
+                    log::warn!("No SG channel found for VRM blend shape {name}");
+                }
+            }
+            binding
+        });
+
+        let mut messages: Vec<_> = self
+            .names
+            .iter()
+            .zip(binding.evaluate(values))
+            .map(|(name, value)| {
+                OscMessage::new(
+                    VMC_BLEND_VAL,
+                    vec![OscArg::String(name.clone()), OscArg::Float(value)],
+                )
+            })
+            .collect();
+        messages.push(OscMessage::new(VMC_BLEND_APPLY, Vec::new()));
+
+        self.buf.clear();
+        encode_bundle(&messages, &mut self.buf);
+        self.socket.send(&self.buf)?;
+        Ok(())
+    }
+}
+
+fn send_vmc(mut sender: ResMut<VmcSender>, anim: Res<FacialAnim>, mut failed: Local<bool>) {
+    let Some(processed_data) = &anim.processed_data else {
+        return;
+    };
+
+    match sender.send_frame(&anim.rig, processed_data) {
+        Ok(()) => *failed = false,
+        Err(e) if !*failed => {
+            log::warn!("Failed to send VMC frame: {e}");
+            *failed = true;
+        }
+        Err(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        output::osc::tests::{decode_bundle, listener, receive},
+        retarget::MorphMapping,
+        track::{NodeType, RigNode},
+    };
+
+    fn rig(channels: &[&str]) -> Rig {
+        Rig {
+            nodes: vec![RigNode {
+                name: "blendBoard".to_string(),
+                node_type: NodeType::BlendShape,
+                channels: channels.iter().map(|channel| channel.to_string()).collect(),
+            }],
+        }
+    }
+
+    fn val(name: &str, value: f32) -> OscMessage {
+        OscMessage::new(
+            VMC_BLEND_VAL,
+            vec![OscArg::String(name.to_string()), OscArg::Float(value)],
+        )
+    }
+
+    #[test]
+    fn sends_every_blend_shape_then_apply() {
+        let (socket, target) = listener();
+        let retarget = RetargetMap {
+            morphs: vec![
+                MorphMapping::new("blendBoard", "jawOpen", "A"),
+                MorphMapping::new("blendBoard", "mouthSmile", "Joy"),
+                MorphMapping::new("blendBoard", "missing", "Blink"),
+            ],
+            ..Default::default()
+        };
+        let mut sender = VmcSender::connect(&target, retarget).unwrap();
+        sender
+            .send_frame(&rig(&["mouthSmile", "jawOpen"]), &[vec![0.25, 0.5]])
+            .unwrap();
+
+        assert_eq!(
+            decode_bundle(&receive(&socket)),
+            [
+                val("A", 0.5),
+                val("Joy", 0.25),
+                // Unbound shapes are still sent, at rest
+                val("Blink", 0.0),
+                OscMessage::new(VMC_BLEND_APPLY, Vec::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rebinds_when_the_rig_changes() {
+        let (socket, target) = listener();
+        let retarget = RetargetMap {
+            morphs: vec![MorphMapping::new("blendBoard", "jawOpen", "A")],
+            ..Default::default()
+        };
+        let mut sender = VmcSender::connect(&target, retarget).unwrap();
+        sender.send_frame(&rig(&["jawOpen"]), &[vec![0.5]]).unwrap();
+        receive(&socket);
+
+        sender
+            .send_frame(&rig(&["mouthSmile", "jawOpen"]), &[vec![0.25, 1.0]])
+            .unwrap();
+        assert_eq!(decode_bundle(&receive(&socket))[0], val("A", 1.0));
+    }
+}
@@ -0,0 +1,299 @@
+use crate::{
+    facial_anim::{process_data, FacialAnim},
+    track::Rig,
+};
+use bevy::{log, prelude::*};
+use serde_json::json;
+use std::{
+    io::{self, ErrorKind},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::Duration,
+};
+use tungstenite::{protocol::WebSocketConfig, Message, WebSocket};
+
+// Clients that fall this far behind are dropped
+const MAX_CLIENT_BACKLOG: usize = 1 << 20;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Serves the rig on connect, then every processed frame, to any number of
+/// WebSocket clients.
+pub struct WebSocketServerPlugin {
+    /// Address to listen on, e.g. `0.0.0.0:9001`
+    pub address: String,
+    pub format: FrameFormat,
+}
+
+impl Plugin for WebSocketServerPlugin {
+    fn build(&self, app: &mut App) {
+        match WebSocketServer::bind(&self.address, self.format) {
+            Ok(server) => {
+                log::info!("Serving frames on ws://{}", self.address);
+                app.insert_resource(server);
+                app.add_systems(PreUpdate, publish_frames.after(process_data));
+            }
+            Err(e) => log::error!("Failed to start WebSocket server on {}: {e}", self.address),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// `{"type": "frame", "time": 1.5, "values": [[...], ...]}` text messages
+    #[default]
+    Json,
+    /// Binary messages of little-endian `f32`s: the time, then every channel in
+    /// rig order, NaN for channels of nodes SG_Com returned no data for
+    Binary,
+}
+
+impl FrameFormat {
+    fn name(self) -> &'static str {
+        match self {
+            FrameFormat::Json => "json",
+            FrameFormat::Binary => "binary",
+        }
+    }
+}
+
+enum Broadcast {
+    /// Sent to every client now and to new clients on connect
+    Rig(Message),
+    Frame(Message),
+}
+
+#[derive(Resource)]
+pub struct WebSocketServer {
+    format: FrameFormat,
+    sender: Sender<Broadcast>,
+    // The animator's retarget generation when the rig was last broadcast
+    sent_rig: Option<u32>,
+}
+
+impl WebSocketServer {
+    pub fn bind(address: &str, format: FrameFormat) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("websocket-server".to_string())
+            .spawn(move || serve(listener, receiver))?;
+
+        Ok(Self {
+            format,
+            sender,
+            sent_rig: None,
+        })
+    }
+
+    fn publish(&mut self, anim: &FacialAnim, time: f32) {
+        let Some(processed_data) = &anim.processed_data else {
+            return;
+        };
+
+        // Swapping the character changes the rig, so clients get it again
+        let generation = anim.retarget_generation();
+        if self.sent_rig != Some(generation) {
+            let rig = json!({
+                "type": "rig",
+                "sg_version": anim.sg_version,
+                "format": self.format.name(),
+                "rig": anim.rig,
+            });
+            self.sent_rig = Some(generation);
+            self.send(Broadcast::Rig(Message::text(rig.to_string())));
+        }
+
+        let frame = match self.format {
+            FrameFormat::Json => {
+                let frame = json!({
+                    "type": "frame",
+                    "time": time,
+                    "values": processed_data,
+                });
+                Message::text(frame.to_string())
+            }
+            FrameFormat::Binary => Message::binary(encode_binary(&anim.rig, time, processed_data)),
+        };
+        self.send(Broadcast::Frame(frame));
+    }
+
+    fn send(&self, broadcast: Broadcast) {
+        if self.sender.send(broadcast).is_err() {
+            log::error!("WebSocket server thread stopped");
+        }
+    }
+}
+
+pub fn encode_binary(rig: &Rig, time: f32, values: &[Vec<f32>]) -> Vec<u8> {
+    let mut data = Vec::with_capacity((rig.channel_count() + 1) * 4);
+    data.extend_from_slice(&time.to_le_bytes());
+    for value in rig.aligned_values(values) {
+        data.extend_from_slice(&value.unwrap_or(f32::NAN).to_le_bytes());
+    }
+    data
+}
+
+fn publish_frames(mut server: ResMut<WebSocketServer>, anim: Res<FacialAnim>, time: Res<Time>) {
+    server.publish(&anim, time.elapsed_secs());
+}
+
+fn serve(listener: TcpListener, receiver: Receiver<Broadcast>) {
+    let mut rig: Option<Message> = None;
+    let mut clients: Vec<WebSocket<TcpStream>> = Vec::new();
+    // Handshakes run on their own threads so a slow client can't hold up the others
+    let (connected_sender, connected) = mpsc::channel();
+
+    loop {
+        loop {
+            match listener.accept() {
+                Ok((stream, addr)) => spawn_handshake(stream, addr, connected_sender.clone()),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::error!("Failed to accept WebSocket client: {e}");
+                    break;
+                }
+            }
+        }
+
+        for (addr, mut client) in connected.try_iter() {
+            log::info!("WebSocket client connected: {addr}");
+            if rig
+                .as_ref()
+                .is_none_or(|rig| send(&mut client, rig.clone()))
+            {
+                clients.push(client);
+            }
+        }
+
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(Broadcast::Rig(message)) => {
+                clients.retain_mut(|client| send(client, message.clone()));
+                rig = Some(message);
+            }
+            Ok(Broadcast::Frame(message)) => {
+                clients.retain_mut(|client| send(client, message.clone()));
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        clients.retain_mut(poll);
+    }
+}
+
+fn spawn_handshake(
+    stream: TcpStream,
+    addr: SocketAddr,
+    connected: Sender<(SocketAddr, WebSocket<TcpStream>)>,
+) {
+    let spawned = thread::Builder::new()
+        .name(format!("websocket-handshake-{addr}"))
+        .spawn(move || match handshake(stream) {
+            // The server is gone if this fails, dropping the client with it
+            Ok(client) => _ = connected.send((addr, client)),
+            Err(e) => log::warn!("WebSocket handshake with {addr} failed: {e}"),
+        });
+    if let Err(e) = spawned {
+        log::error!("Failed to start WebSocket handshake with {addr}: {e}");
+    }
+}
+
+fn handshake(stream: TcpStream) -> Result<WebSocket<TcpStream>, Box<dyn std::error::Error>> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    stream.set_nodelay(true)?;
+
+    let config = WebSocketConfig::default()
+        .write_buffer_size(0)
+        .max_write_buffer_size(MAX_CLIENT_BACKLOG);
+    let client =
+        tungstenite::accept_with_config(stream, Some(config)).map_err(|e| e.to_string())?;
+    client.get_ref().set_nonblocking(true)?;
+    Ok(client)
+}
+
+// Returns false once the client should be dropped
+fn send(client: &mut WebSocket<TcpStream>, message: Message) -> bool {
+    match client.send(message) {
+        Ok(()) => true,
+        // Still buffered; flushed by a later send or poll
+        Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => true,
+        Err(_) => false,
+    }
+}
+
+// Handles pings and close requests; client messages are otherwise ignored
+fn poll(client: &mut WebSocket<TcpStream>) -> bool {
+    loop {
+        match client.read() {
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(_) => return false,
+        }
+    }
+
+    match client.flush() {
+        Ok(()) => true,
+        Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => true,
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::{NodeType, RigNode};
+    use std::time::Instant;
+
+    #[test]
+    fn binary_frames_fill_missing_nodes_with_nan() {
+        let node = |name: &str, channels: usize| RigNode {
+            name: name.to_string(),
+            node_type: NodeType::Control,
+            channels: (0..channels).map(|index| index.to_string()).collect(),
+        };
+        let rig = Rig {
+            nodes: vec![node("jaw", 1), node("tongue", 2), node("lips", 1)],
+        };
+
+        let data = encode_binary(&rig, 1.5, &[vec![0.5], Vec::new(), vec![0.25]]);
+        let values: Vec<f32> = data
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+
+        assert_eq!(values.len(), 5);
+        assert_eq!(values[..2], [1.5, 0.5]);
+        assert!(values[2].is_nan() && values[3].is_nan());
+        assert_eq!(values[4], 0.25);
+    }
+
+    #[test]
+    fn stalled_handshake_doesnt_block_other_clients() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        let server = WebSocketServer::bind(&address.to_string(), FrameFormat::Json).unwrap();
+        server.send(Broadcast::Rig(Message::text("rig")));
+
+        // Connects but never sends its handshake request; it's accepted first
+        let _stalled = TcpStream::connect(address).unwrap();
+
+        let started = Instant::now();
+        let stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let (mut client, _) = tungstenite::client(format!("ws://{address}"), stream).unwrap();
+
+        // The rig arrives once the server has taken the client on
+        assert_eq!(client.read().unwrap(), Message::text("rig"));
+        server.send(Broadcast::Frame(Message::text("frame")));
+        assert_eq!(client.read().unwrap(), Message::text("frame"));
+        assert!(started.elapsed() < HANDSHAKE_TIMEOUT);
+    }
+}
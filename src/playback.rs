@@ -0,0 +1,253 @@
+use crate::{
+    audio::{
+        decode,
+        output::{self, OutputSource},
+        resample,
+    },
+    export,
+    facial_anim::SendStream,
+    track::Track,
+};
+use bevy::log;
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, Sample,
+};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// Plays a track file back in place of SG_Com, along with its audio if it has any.
+pub struct TrackPlayback {
+    track: Track,
+    audio: Option<PlaybackAudio>,
+    elapsed: Duration,
+}
+
+struct PlaybackAudio {
+    stream: Mutex<SendStream>,
+    clock: AudioClock,
+}
+
+// How much of the audio the device has played
+struct AudioClock {
+    // Interleaved samples written to the device so far
+    position: Arc<AtomicUsize>,
+    len: usize,
+    samples_per_second: f32,
+}
+
+impl AudioClock {
+    // `elapsed` takes over once the audio runs out
+    fn seconds_or(&self, elapsed: Duration) -> f32 {
+        let position = self.position.load(Ordering::Relaxed);
+        if position < self.len {
+            position as f32 / self.samples_per_second
+        } else {
+            elapsed.as_secs_f32()
+        }
+    }
+}
+
+impl TrackPlayback {
+    pub fn open(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let track = export::json::read_file(&path)?;
+        log::info!(
+            "Playing back {} ({} frames, {:.1}s)",
+            path.display(),
+            track.frames.len(),
+            track.duration().as_secs_f32()
+        );
+
+        let audio = track.audio.as_ref().and_then(|audio| {
+            let audio_path = path.parent().unwrap_or(&path).join(audio);
+            PlaybackAudio::open(&audio_path)
+                .inspect_err(|e| log::warn!("Playing {} without audio: {e}", path.display()))
+                .ok()
+        });
+
+        Ok(Self {
+            track,
+            audio,
+            elapsed: Duration::ZERO,
+        })
+    }
+
+    pub fn track(&self) -> &Track {
+        &self.track
+    }
+
+    pub fn start(&mut self) {
+        self.elapsed = Duration::ZERO;
+        if let Some(audio) = &self.audio {
+            audio.clock.position.store(0, Ordering::Relaxed);
+            if let Err(e) = audio.stream.lock().unwrap().0.play() {
+                log::warn!("Failed to begin playback audio: {e}");
+            }
+        }
+    }
+
+    /// Seconds into the track, following the audio device while the audio lasts.
+    pub fn position(&self) -> f32 {
+        match &self.audio {
+            Some(audio) => audio.clock.seconds_or(self.elapsed),
+            None => self.elapsed.as_secs_f32(),
+        }
+    }
+
+    pub fn advance(&mut self, delta: Duration) -> Vec<Vec<f32>> {
+        self.elapsed += delta;
+        self.sample(self.position())
+    }
+
+    /// Linearly interpolates the frames around `time`, holding the ends and
+    /// any frame whose neighbour has a different layout.
+    pub fn sample(&self, time: f32) -> Vec<Vec<f32>> {
+        let frames = &self.track.frames;
+        let next = frames.partition_point(|frame| frame.time <= time);
+        match (next.checked_sub(1).map(|i| &frames[i]), frames.get(next)) {
+            (Some(a), Some(b)) if same_layout(&a.values, &b.values) => {
+                let t = ((time - a.time) / (b.time - a.time)).clamp(0.0, 1.0);
+                a.values
+                    .iter()
+                    .zip(&b.values)
+                    .map(|(a, b)| a.iter().zip(b).map(|(a, b)| a + (b - a) * t).collect())
+                    .collect()
+            }
+            (Some(frame), _) | (None, Some(frame)) => frame.values.clone(),
+            (None, None) => Vec::new(),
+        }
+    }
+}
+
+fn same_layout(a: &[Vec<f32>], b: &[Vec<f32>]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.len() == b.len())
+}
+
+impl PlaybackAudio {
+    fn open(path: &std::path::Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let audio = decode::decode_file(path)?;
+
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("No output device available")?;
+        let config = device.default_output_config()?;
+        let channels = config.channels() as usize;
+
+        let audio = audio.resample(config.sample_rate().0);
+        let samples: Arc<[f32]> = resample::remix(&audio.samples, audio.channels, channels).into();
+
+        let position = Arc::new(AtomicUsize::new(0));
+        let source = PlaybackSource {
+            samples: samples.clone(),
+            position: position.clone(),
+        };
+        let stream = output::build_output_stream(&device, &config, source, |err| {
+            log::error!("Playback stream error: {}", err)
+        })?;
+
+        Ok(Self {
+            stream: Mutex::new(SendStream(stream)),
+            clock: AudioClock {
+                position,
+                len: samples.len(),
+                samples_per_second: (config.sample_rate().0 as usize * channels) as f32,
+            },
+        })
+    }
+}
+
+// Plays the samples once, then silence, counting every sample written
+struct PlaybackSource {
+    samples: Arc<[f32]>,
+    position: Arc<AtomicUsize>,
+}
+
+impl OutputSource for PlaybackSource {
+    fn fill<T>(&mut self, data: &mut [T])
+    where
+        T: Sample + FromSample<f32>,
+    {
+        let start = self.position.load(Ordering::Relaxed);
+        for (i, out) in data.iter_mut().enumerate() {
+            *out = self
+                .samples
+                .get(start + i)
+                .map_or(T::EQUILIBRIUM, |sample| T::from_sample(*sample));
+        }
+        self.position.store(start + data.len(), Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::{NodeType, Rig, RigNode};
+
+    fn playback(frames: &[(f32, Vec<f32>)]) -> TrackPlayback {
+        let rig = Rig {
+            nodes: vec![RigNode {
+                name: "blendBoard".to_string(),
+                node_type: NodeType::BlendShape,
+                channels: vec!["jawOpen".to_string(), "mouthSmile".to_string()],
+            }],
+        };
+        let mut track = Track::new(rig, 10.0);
+        for (time, values) in frames {
+            track.push(*time, vec![values.clone()]);
+        }
+        TrackPlayback {
+            track,
+            audio: None,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn interpolates_between_frames() {
+        let track = playback(&[(0.25, vec![0.0, 1.0]), (0.75, vec![1.0, 0.0])]);
+        assert_eq!(track.sample(0.5), [[0.5, 0.5]]);
+        // The ends hold
+        assert_eq!(track.sample(0.0), [[0.0, 1.0]]);
+        assert_eq!(track.sample(0.75), [[1.0, 0.0]]);
+        assert_eq!(track.sample(5.0), [[1.0, 0.0]]);
+    }
+
+    #[test]
+    fn holds_frames_whose_neighbours_lost_values() {
+        let track = playback(&[(0.25, vec![0.0, 1.0]), (0.75, vec![])]);
+        assert_eq!(track.sample(0.5), [[0.0, 1.0]]);
+        assert!(playback(&[]).sample(0.0).is_empty());
+    }
+
+    #[test]
+    fn follows_the_audio_until_it_runs_out() {
+        let position = Arc::new(AtomicUsize::new(0));
+        let mut source = PlaybackSource {
+            samples: vec![0.5; 6].into(),
+            position: position.clone(),
+        };
+        let clock = AudioClock {
+            position,
+            len: 6,
+            samples_per_second: 2.0,
+        };
+        let elapsed = Duration::from_secs(10);
+
+        let mut data = [0i16; 4];
+        source.fill(&mut data);
+        assert_eq!(data, [0.5f32.to_sample::<i16>(); 4]);
+        assert_eq!(clock.seconds_or(elapsed), 2.0);
+
+        source.fill(&mut data);
+        assert_eq!(data[..2], [0.5f32.to_sample::<i16>(); 2]);
+        assert_eq!(data[2..], [0; 2]);
+        assert_eq!(clock.seconds_or(elapsed), 10.0);
+    }
+}
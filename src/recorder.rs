@@ -0,0 +1,427 @@
+use crate::{
+    audio::resample::{self, Resampler},
+    export,
+    facial_anim::{process_data, FacialAnim},
+    track::Track,
+};
+use bevy::{log, prelude::*};
+use cpal::{FromSample, Sample, StreamConfig};
+use crossbeam_deque::{Injector, Steal};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+pub struct SessionRecorderPlugin {
+    /// Written to sessions too short to measure their own frame rate
+    pub frame_rate: f32,
+}
+
+impl Plugin for SessionRecorderPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SessionRecorder {
+            request: None,
+            session: None,
+            frame_rate: self.frame_rate,
+        });
+        app.add_systems(PreUpdate, record_session.after(process_data));
+        app.add_systems(Last, save_on_exit);
+    }
+}
+
+/// Channel count and sample rate of tapped audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TapFormat {
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+impl From<&StreamConfig> for TapFormat {
+    fn from(config: &StreamConfig) -> Self {
+        Self {
+            channels: config.channels,
+            sample_rate: config.sample_rate.0,
+        }
+    }
+}
+
+// Interleaved samples in the format of the stream they came from
+struct TapBuffer {
+    format: TapFormat,
+    samples: Vec<f32>,
+}
+
+/// Copies raw input buffers out of the audio callback while a recording is running.
+/// Outlives the input streams, so each stream pushes through its own `StreamTap`.
+#[derive(Clone, Default)]
+pub struct InputTap {
+    enabled: Arc<AtomicBool>,
+    queue: Arc<Injector<TapBuffer>>,
+}
+
+/// An `InputTap` attached to one stream, tagging its buffers with the stream's format.
+pub struct StreamTap {
+    tap: InputTap,
+    format: TapFormat,
+}
+
+impl StreamTap {
+    pub fn push<T>(&self, data: &[T])
+    where
+        T: Sample,
+        f32: FromSample<T>,
+    {
+        if self.tap.enabled.load(Ordering::Relaxed) {
+            self.tap.queue.push(TapBuffer {
+                format: self.format,
+                samples: data.iter().map(|sample| sample.to_sample()).collect(),
+            });
+        }
+    }
+}
+
+impl InputTap {
+    pub fn for_stream(&self, config: &StreamConfig) -> StreamTap {
+        StreamTap {
+            tap: self.clone(),
+            format: config.into(),
+        }
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    fn drain(&self) -> impl Iterator<Item = TapBuffer> + '_ {
+        std::iter::from_fn(|| loop {
+            match self.queue.steal() {
+                Steal::Success(data) => return Some(data),
+                Steal::Empty => return None,
+                Steal::Retry => continue,
+            }
+        })
+    }
+}
+
+enum Request {
+    Start(PathBuf),
+    Stop,
+}
+
+// Converts tapped buffers to the format a session's WAV was opened with
+struct TapConverter {
+    format: TapFormat,
+    // Set up for the last foreign sample rate seen
+    resampler: Option<(u32, Resampler)>,
+}
+
+impl TapConverter {
+    fn new(format: TapFormat) -> Self {
+        Self {
+            format,
+            resampler: None,
+        }
+    }
+
+    fn convert(&mut self, buffer: TapBuffer) -> Vec<f32> {
+        if buffer.format == self.format {
+            return buffer.samples;
+        }
+
+        // The input device changed mid-session
+        let channels = self.format.channels as usize;
+        let samples = resample::remix(&buffer.samples, buffer.format.channels as usize, channels);
+        let from = buffer.format.sample_rate;
+        let resampler = match &mut self.resampler {
+            Some((rate, resampler)) if *rate == from => resampler,
+            resampler => {
+                let new = Resampler::new(from, self.format.sample_rate, channels);
+                &mut resampler.insert((from, new)).1
+            }
+        };
+        let mut output = Vec::new();
+        resampler.process(&samples, &mut output);
+        output
+    }
+}
+
+struct Session {
+    path: PathBuf,
+    wav: hound::WavWriter<BufWriter<File>>,
+    converter: TapConverter,
+    track: Track,
+    started: Duration,
+}
+
+/// Records a live session to `<path>.wav` (the raw microphone input, kept in
+/// the format it started in if the device changes) and
+/// `<path>.track.json` (every processed frame, timestamped from the start).
+/// Recording stops if the rig changes, since a track holds a single rig.
+#[derive(Resource)]
+pub struct SessionRecorder {
+    request: Option<Request>,
+    session: Option<Session>,
+    frame_rate: f32,
+}
+
+impl SessionRecorder {
+    pub fn start(&mut self, path: impl Into<PathBuf>) {
+        self.request = Some(Request::Start(path.into()));
+    }
+
+    pub fn stop(&mut self) {
+        self.request = Some(Request::Stop);
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.session.is_some()
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.session.as_ref().map(|session| session.path.as_path())
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+fn open_session(
+    path: PathBuf,
+    anim: &FacialAnim,
+    config: &StreamConfig,
+    frame_rate: f32,
+    time: &Time,
+) -> hound::Result<Session> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let spec = hound::WavSpec {
+        channels: config.channels,
+        sample_rate: config.sample_rate.0,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let wav = hound::WavWriter::create(with_suffix(&path, ".wav"), spec)?;
+
+    let mut track = Track::new(anim.rig.clone(), frame_rate);
+    track.sg_version = anim.sg_version.clone();
+    track.audio = path
+        .file_name()
+        .map(|name| format!("{}.wav", name.to_string_lossy()));
+
+    Ok(Session {
+        path,
+        wav,
+        converter: TapConverter::new(config.into()),
+        track,
+        started: time.elapsed(),
+    })
+}
+
+fn close_session(mut session: Session) -> hound::Result<()> {
+    session.wav.finalize()?;
+
+    // Otherwise the track keeps the configured rate it was opened with
+    let duration = session.track.duration().as_secs_f32();
+    if session.track.frames.len() > 1 && duration > 0.0 {
+        session.track.frame_rate = (session.track.frames.len() - 1) as f32 / duration;
+    }
+    export::json::write_file(&session.track, with_suffix(&session.path, ".track.json"))?;
+    Ok(())
+}
+
+pub(crate) fn record_session(
+    mut recorder: ResMut<SessionRecorder>,
+    anim: Res<FacialAnim>,
+    time: Res<Time>,
+) {
+    let Some(tap) = anim.tap() else {
+        if let Some(Request::Start(_)) = recorder.request.take() {
+            log::warn!("Recording is only available with live input");
+        }
+        return;
+    };
+
+    match recorder.request.take() {
+        Some(Request::Start(path)) => {
+            if let Some(session) = recorder.session.take() {
+                save_session(session, Some(tap));
+            }
+            let Some((_, config)) = anim.input() else {
+                log::warn!("Can't start recording while no input device is open");
+                return;
+            };
+
+            match open_session(path, &anim, config, recorder.frame_rate, &time) {
+                Ok(session) => {
+                    log::info!("Recording session to {}", session.path.display());
+                    // Drop anything left over from a previous session
+                    tap.drain().for_each(drop);
+                    tap.set_enabled(true);
+                    recorder.session = Some(session);
+                }
+                Err(e) => log::error!("Failed to start recording: {e}"),
+            }
+        }
+        Some(Request::Stop) => {
+            if let Some(session) = recorder.session.take() {
+                save_session(session, Some(tap));
+            }
+            return;
+        }
+        None => {}
+    }
+
+    let Some(session) = &mut recorder.session else {
+        return;
+    };
+    if session.track.rig != anim.rig {
+        log::warn!("The rig changed, so recording stopped");
+        let session = recorder.session.take().unwrap();
+        save_session(session, Some(tap));
+        return;
+    }
+
+    write_audio(session, tap);
+    if let Some(processed_data) = &anim.processed_data {
+        let time = (time.elapsed() - session.started).as_secs_f32();
+        session.track.push(time, processed_data.clone());
+    }
+}
+
+// Without a tap, whatever audio was already written is kept along with the track
+fn save_session(mut session: Session, tap: Option<&InputTap>) {
+    if let Some(tap) = tap {
+        tap.set_enabled(false);
+        write_audio(&mut session, tap);
+    }
+    let path = session.path.clone();
+    match close_session(session) {
+        Ok(()) => log::info!("Saved session to {}", path.display()),
+        Err(e) => log::error!("Failed to save session: {e}"),
+    }
+}
+
+// A session still running when the app exits (window closed, Ctrl+C) would be lost otherwise
+fn save_on_exit(
+    mut exit: EventReader<AppExit>,
+    mut recorder: ResMut<SessionRecorder>,
+    anim: Res<FacialAnim>,
+) {
+    if exit.read().last().is_none() {
+        return;
+    }
+    if let Some(session) = recorder.session.take() {
+        save_session(session, anim.tap());
+    }
+}
+
+fn write_audio(session: &mut Session, tap: &InputTap) {
+    for buffer in tap.drain() {
+        for sample in session.converter.convert(buffer) {
+            if let Err(e) = session.wav.write_sample(sample) {
+                log::error!("Failed to write session audio: {e}");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpal::SampleRate;
+
+    fn config(channels: u16, sample_rate: u32) -> StreamConfig {
+        StreamConfig {
+            channels,
+            sample_rate: SampleRate(sample_rate),
+            buffer_size: cpal::BufferSize::Default,
+        }
+    }
+
+    #[test]
+    fn tags_buffers_with_their_stream_format() {
+        let tap = InputTap::default();
+        let stereo = tap.for_stream(&config(2, 48000));
+        let mono = tap.for_stream(&config(1, 16000));
+
+        stereo.push(&[0.5f32, 0.25]);
+        tap.set_enabled(true);
+        stereo.push(&[i16::MAX, 0]);
+        mono.push(&[0.75f32]);
+
+        let buffers: Vec<_> = tap.drain().collect();
+        assert_eq!(buffers.len(), 2);
+        assert_eq!(buffers[0].format, (&config(2, 48000)).into());
+        assert_eq!(buffers[0].samples, [i16::MAX.to_sample::<f32>(), 0.0]);
+        assert_eq!(buffers[1].format, (&config(1, 16000)).into());
+        assert_eq!(buffers[1].samples, [0.75]);
+    }
+
+    #[test]
+    fn converts_buffers_to_the_session_format() {
+        let mut converter = TapConverter::new((&config(1, 16000)).into());
+
+        let same = TapBuffer {
+            format: (&config(1, 16000)).into(),
+            samples: vec![0.5; 4],
+        };
+        assert_eq!(converter.convert(same), [0.5; 4]);
+
+        // Stereo at three times the rate comes out mono at a third of the length
+        let stereo = TapBuffer {
+            format: (&config(2, 48000)).into(),
+            samples: [1.0, 0.0].repeat(300),
+        };
+        let converted = converter.convert(stereo);
+        assert!((99..=100).contains(&converted.len()));
+        assert!(converted.iter().all(|sample| *sample == 0.5));
+    }
+
+    fn closed_frame_rate(times: &[f32]) -> f32 {
+        let dir = std::env::temp_dir().join(format!("sg-com-recorder-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("session-{}", times.len()));
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 16000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut track = Track::new(Default::default(), 60.0);
+        for time in times {
+            track.push(*time, Vec::new());
+        }
+        let session = Session {
+            wav: hound::WavWriter::create(with_suffix(&path, ".wav"), spec).unwrap(),
+            path: path.clone(),
+            converter: TapConverter::new((&config(1, 16000)).into()),
+            track,
+            started: Duration::ZERO,
+        };
+
+        close_session(session).unwrap();
+        let track = export::json::read_file(with_suffix(&path, ".track.json")).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        track.frame_rate
+    }
+
+    #[test]
+    fn measures_the_session_frame_rate() {
+        assert_eq!(closed_frame_rate(&[0.0, 0.25, 0.5]), 4.0);
+        // One frame has no interval to measure, so the configured rate stays
+        assert_eq!(closed_frame_rate(&[0.5]), 60.0);
+        assert_eq!(closed_frame_rate(&[]), 60.0);
+    }
+}
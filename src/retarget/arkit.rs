@@ -0,0 +1,190 @@
+use super::{MixMode, MorphMapping, RetargetMap, POSE_NODE};
+
+/// The ARKit 52 blend shapes, in the order Live Link Face sends them.
+pub const ARKIT_BLENDSHAPES: [&str; 52] = [
+    "eyeBlinkLeft",
+    "eyeLookDownLeft",
+    "eyeLookInLeft",
+    "eyeLookOutLeft",
+    "eyeLookUpLeft",
+    "eyeSquintLeft",
+    "eyeWideLeft",
+    "eyeBlinkRight",
+    "eyeLookDownRight",
+    "eyeLookInRight",
+    "eyeLookOutRight",
+    "eyeLookUpRight",
+    "eyeSquintRight",
+    "eyeWideRight",
+    "jawForward",
+    "jawLeft",
+    "jawRight",
+    "jawOpen",
+    "mouthClose",
+    "mouthFunnel",
+    "mouthPucker",
+    "mouthLeft",
+    "mouthRight",
+    "mouthSmileLeft",
+    "mouthSmileRight",
+    "mouthFrownLeft",
+    "mouthFrownRight",
+    "mouthDimpleLeft",
+    "mouthDimpleRight",
+    "mouthStretchLeft",
+    "mouthStretchRight",
+    "mouthRollLower",
+    "mouthRollUpper",
+    "mouthShrugLower",
+    "mouthShrugUpper",
+    "mouthPressLeft",
+    "mouthPressRight",
+    "mouthLowerDownLeft",
+    "mouthLowerDownRight",
+    "mouthUpperUpLeft",
+    "mouthUpperUpRight",
+    "browDownLeft",
+    "browDownRight",
+    "browInnerUp",
+    "browOuterUpLeft",
+    "browOuterUpRight",
+    "cheekPuff",
+    "cheekSquintLeft",
+    "cheekSquintRight",
+    "noseSneerLeft",
+    "noseSneerRight",
+    "tongueOut",
+];
+
+// `blendBoard` channel, ARKit shape, gain. A shape ending in `*` is a left/right
+// pair driven together by an unsided channel. Secondary shapes (the cheek and eye
+// squints of a smile, the outer brows of a raise) follow at reduced gain. The
+// channel names are placeholders following ARKit naming, not read from the shipped
+// character; `reads_channels_of_the_shipped_character` checks them where SG_Com is
+// available.
+const ARKIT_TABLE: &[(&str, &str, f32)] = &[
+    ("jawOpen", "jawOpen", 1.0),
+    ("jawForward", "jawForward", 1.0),
+    ("mouthClose", "mouthClose", 1.0),
+    ("mouthFunnel", "mouthFunnel", 1.0),
+    ("mouthPucker", "mouthPucker", 1.0),
+    ("mouthSmile", "mouthSmile*", 1.0),
+    ("mouthSmile", "cheekSquint*", 0.4),
+    ("mouthSmile", "eyeSquint*", 0.15),
+    ("mouthFrown", "mouthFrown*", 1.0),
+    ("mouthDimple", "mouthDimple*", 1.0),
+    ("mouthStretch", "mouthStretch*", 1.0),
+    ("mouthPress", "mouthPress*", 1.0),
+    ("mouthLowerDown", "mouthLowerDown*", 1.0),
+    ("mouthUpperUp", "mouthUpperUp*", 1.0),
+    ("mouthRollLower", "mouthRollLower", 1.0),
+    ("mouthRollUpper", "mouthRollUpper", 1.0),
+    ("mouthShrugLower", "mouthShrugLower", 1.0),
+    ("mouthShrugUpper", "mouthShrugUpper", 1.0),
+    ("cheekPuff", "cheekPuff", 1.0),
+    ("tongueOut", "tongueOut", 1.0),
+    ("eyeBlink", "eyeBlink*", 1.0),
+    ("eyeBlinkLeft", "eyeBlinkLeft", 1.0),
+    ("eyeBlinkRight", "eyeBlinkRight", 1.0),
+    ("browUp", "browInnerUp", 1.0),
+    ("browUp", "browOuterUp*", 0.8),
+    ("browDown", "browDown*", 1.0),
+    ("noseSneer", "noseSneer*", 1.0),
+];
+
+/// Translates the SG control board onto the ARKit 52 blend shapes: unsided
+/// board channels are split onto both sides of their ARKit pair, and a few
+/// channels also drive secondary shapes at reduced gain. Weights are clamped
+/// to `0..1`, and where several channels reach one shape the strongest wins.
+/// Shapes the board has no control for (eye look, jaw and mouth sideways) stay
+/// at rest.
+pub fn preset() -> RetargetMap {
+    let morphs = ARKIT_TABLE
+        .iter()
+        .flat_map(|(channel, shape, gain)| {
+            let shapes = match shape.strip_suffix('*') {
+                Some(pair) => vec![format!("{pair}Left"), format!("{pair}Right")],
+                None => vec![shape.to_string()],
+            };
+            shapes.into_iter().map(|shape| {
+                let mut mapping = MorphMapping::new(POSE_NODE, *channel, shape);
+                mapping.gain = *gain;
+                mapping.clamp = Some([0.0, 1.0]);
+                mapping
+            })
+        })
+        .collect();
+
+    RetargetMap {
+        morphs,
+        mix: MixMode::Max,
+        bones: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::{NodeType, Rig, RigNode};
+
+    fn board(channels: &[&str]) -> Rig {
+        Rig {
+            nodes: vec![RigNode {
+                name: POSE_NODE.to_string(),
+                node_type: NodeType::BlendShape,
+                channels: channels.iter().map(|channel| channel.to_string()).collect(),
+            }],
+        }
+    }
+
+    fn arkit_names() -> Vec<String> {
+        ARKIT_BLENDSHAPES.map(str::to_owned).to_vec()
+    }
+
+    #[test]
+    fn only_drives_arkit_shapes() {
+        for name in preset().morph_names() {
+            assert!(ARKIT_BLENDSHAPES.contains(&name.as_str()), "{name}");
+        }
+    }
+
+    #[test]
+    fn splits_unsided_channels_with_secondary_gains() {
+        let rig = board(&["mouthSmile", "browUp"]);
+        let binding = preset().bind(&rig, &arkit_names());
+        let weights = binding.evaluate(&[vec![0.5, 2.0]]);
+        let weight =
+            |name: &str| weights[ARKIT_BLENDSHAPES.iter().position(|n| *n == name).unwrap()];
+
+        assert_eq!(weight("mouthSmileLeft"), 0.5);
+        assert_eq!(weight("mouthSmileRight"), 0.5);
+        assert_eq!(weight("cheekSquintLeft"), 0.2);
+        assert_eq!(weight("cheekSquintRight"), 0.2);
+        // Clamped to 1
+        assert_eq!(weight("browInnerUp"), 1.0);
+        assert_eq!(weight("browOuterUpLeft"), 1.0);
+        assert_eq!(weight("jawOpen"), 0.0);
+    }
+
+    #[test]
+    fn sided_and_unsided_channels_take_the_strongest() {
+        let rig = board(&["eyeBlink", "eyeBlinkLeft"]);
+        let binding = preset().bind(&rig, &arkit_names());
+        let weights = binding.evaluate(&[vec![0.25, 0.75]]);
+        let weight =
+            |name: &str| weights[ARKIT_BLENDSHAPES.iter().position(|n| *n == name).unwrap()];
+
+        assert_eq!(weight("eyeBlinkLeft"), 0.75);
+        assert_eq!(weight("eyeBlinkRight"), 0.25);
+    }
+
+    #[cfg(feature = "runtime")]
+    #[test]
+    #[ignore = "needs the SG_Com runtime"]
+    fn reads_channels_of_the_shipped_character() {
+        assert_eq!(
+            crate::retarget::missing_on_shipped_character(&preset()),
+            Vec::<&str>::new()
+        );
+    }
+}
@@ -0,0 +1,816 @@
+use crate::track::{NodeType, Rig, RigNode, Track};
+use bevy::math::{Quat, Vec3};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+pub mod arkit;
+pub mod vrm;
+
+pub const POSE_NODE: &str = "blendBoard";
+pub const POSE_SUFFIX: &str = "_pose";
+
+/// Response curve applied to a channel value before gain and offset.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Curve {
+    #[default]
+    Linear,
+    SmoothStep,
+    EaseIn,
+    EaseOut,
+    /// `value^exponent`, keeping the sign; the exponent must be positive
+    Power(#[serde(deserialize_with = "positive_exponent")] f32),
+    /// Piecewise linear `[input, output]` points sorted by input
+    Points(Vec<[f32; 2]>),
+}
+
+impl Curve {
+    pub fn sample(&self, value: f32) -> f32 {
+        match self {
+            Curve::Linear => value,
+            Curve::SmoothStep => {
+                let t = value.clamp(0.0, 1.0);
+                t * t * (3.0 - 2.0 * t)
+            }
+            Curve::EaseIn => value * value.abs(),
+            Curve::EaseOut => {
+                let t = 1.0 - value.clamp(0.0, 1.0);
+                1.0 - t * t
+            }
+            Curve::Power(exponent) => value.abs().powf(*exponent).copysign(value),
+            Curve::Points(points) => sample_points(points, value),
+        }
+    }
+}
+
+// A zero or negative exponent sends 0 to infinity or NaN, so it's refused on load
+fn positive_exponent<'de, D>(deserializer: D) -> Result<f32, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let exponent = f32::deserialize(deserializer)?;
+    if exponent.is_finite() && exponent > 0.0 {
+        Ok(exponent)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "power exponent must be positive, got {exponent}"
+        )))
+    }
+}
+
+fn sample_points(points: &[[f32; 2]], value: f32) -> f32 {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return value;
+    };
+    if value <= first[0] {
+        return first[1];
+    }
+    if value >= last[0] {
+        return last[1];
+    }
+
+    points
+        .windows(2)
+        .find(|pair| value <= pair[1][0])
+        .map(|pair| {
+            let [[x0, y0], [x1, y1]] = [pair[0], pair[1]];
+            if x1 > x0 {
+                y0 + (y1 - y0) * (value - x0) / (x1 - x0)
+            } else {
+                y1
+            }
+        })
+        .unwrap_or(last[1])
+}
+
+/// Built-in maps, selectable by name wherever a retarget file is accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// `<channel>_pose` morphs, see `RetargetMap::pose_suffix`
+    PoseSuffix,
+    Arkit,
+    Vrm,
+}
+
+impl Preset {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "pose" | "pose_suffix" => Some(Preset::PoseSuffix),
+            "arkit" => Some(Preset::Arkit),
+            "vrm" => Some(Preset::Vrm),
+            _ => None,
+        }
+    }
+
+    pub fn map(self, rig: &Rig) -> RetargetMap {
+        match self {
+            Preset::PoseSuffix => RetargetMap::pose_suffix(rig),
+            Preset::Arkit => RetargetMap::arkit(),
+            Preset::Vrm => RetargetMap::vrm(),
+        }
+    }
+}
+
+/// How several mappings onto the same morph are combined.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MixMode {
+    #[default]
+    Add,
+    Max,
+    Average,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn is_one(value: &f32) -> bool {
+    *value == 1.0
+}
+
+fn is_zero(value: &f32) -> bool {
+    *value == 0.0
+}
+
+fn is_linear(curve: &Curve) -> bool {
+    *curve == Curve::Linear
+}
+
+/// Drives `morph` from one SG channel: `clamp(curve(value) * gain + offset)`.
+/// A channel can appear in several mappings to drive more than one morph.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MorphMapping {
+    pub node: String,
+    pub channel: String,
+    pub morph: String,
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    pub gain: f32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub offset: f32,
+    /// `[min, max]` applied last
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clamp: Option<[f32; 2]>,
+    #[serde(default, skip_serializing_if = "is_linear")]
+    pub curve: Curve,
+}
+
+impl MorphMapping {
+    pub fn new(
+        node: impl Into<String>,
+        channel: impl Into<String>,
+        morph: impl Into<String>,
+    ) -> Self {
+        Self {
+            node: node.into(),
+            channel: channel.into(),
+            morph: morph.into(),
+            gain: 1.0,
+            offset: 0.0,
+            clamp: None,
+            curve: Curve::Linear,
+        }
+    }
+
+    pub fn response(&self, value: f32) -> f32 {
+        let value = self.curve.sample(value) * self.gain + self.offset;
+        match self.clamp {
+            Some([min, max]) => value.clamp(min, max),
+            None => value,
+        }
+    }
+}
+
+/// Whether a bone mapping rotates its joint or slides it along `axis`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoneMotion {
+    #[default]
+    Rotate,
+    Translate,
+}
+
+fn x_axis() -> [f32; 3] {
+    [1.0, 0.0, 0.0]
+}
+
+fn is_rotate(motion: &BoneMotion) -> bool {
+    *motion == BoneMotion::Rotate
+}
+
+/// Drives the joint named `bone` from one SG channel. The channel value,
+/// clamped to `0..1`, picks an amount between `range[0]` and `range[1]`:
+/// degrees around `axis` for rotations, model units along it for translations.
+/// `axis` is in the joint's local space.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoneMapping {
+    pub node: String,
+    pub channel: String,
+    pub bone: String,
+    #[serde(default = "x_axis")]
+    pub axis: [f32; 3],
+    pub range: [f32; 2],
+    #[serde(default, skip_serializing_if = "is_rotate")]
+    pub motion: BoneMotion,
+    /// Rest rotation as XYZ euler degrees; the joint's transform when it's
+    /// first bound is used when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rest: Option<[f32; 3]>,
+}
+
+impl BoneMapping {
+    pub fn new(
+        node: impl Into<String>,
+        channel: impl Into<String>,
+        bone: impl Into<String>,
+        range: [f32; 2],
+    ) -> Self {
+        Self {
+            node: node.into(),
+            channel: channel.into(),
+            bone: bone.into(),
+            axis: x_axis(),
+            range,
+            motion: BoneMotion::Rotate,
+            rest: None,
+        }
+    }
+
+    pub fn amount(&self, value: f32) -> f32 {
+        let [min, max] = self.range;
+        min + (max - min) * value.clamp(0.0, 1.0)
+    }
+
+    pub fn rest_rotation(&self) -> Option<Quat> {
+        self.rest.map(|[x, y, z]| {
+            Quat::from_euler(
+                bevy::math::EulerRot::XYZ,
+                x.to_radians(),
+                y.to_radians(),
+                z.to_radians(),
+            )
+        })
+    }
+}
+
+/// Maps SG output channels onto named morph targets and joints.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RetargetMap {
+    pub morphs: Vec<MorphMapping>,
+    #[serde(default)]
+    pub mix: MixMode,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bones: Vec<BoneMapping>,
+}
+
+impl RetargetMap {
+    /// Every `blendBoard` channel drives the morph target named `<channel>_pose`.
+    pub fn pose_suffix(rig: &Rig) -> Self {
+        Self {
+            morphs: rig
+                .nodes
+                .iter()
+                .filter(|node| node.name == POSE_NODE)
+                .flat_map(|node| {
+                    node.channels.iter().map(|channel| {
+                        MorphMapping::new(&node.name, channel, format!("{channel}{POSE_SUFFIX}"))
+                    })
+                })
+                .collect(),
+            mix: MixMode::default(),
+            bones: Vec::new(),
+        }
+    }
+
+    /// Translates the `blendBoard` channels onto the ARKit 52 blend shapes,
+    /// see `arkit::preset`.
+    pub fn arkit() -> Self {
+        arkit::preset()
+    }
+
+    /// Drives VRM 0.x blend shapes (`A`, `I`, `U`, `E`, `O`, `Blink`, ...), see `vrm::preset`.
+    pub fn vrm() -> Self {
+        vrm::preset()
+    }
+
+    /// Reads a map from RON (`.ron`) or JSON (anything else).
+    pub fn read_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let reader = BufReader::new(File::open(path)?);
+        if is_ron(path) {
+            ron::de::from_reader(reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(serde_json::from_reader(reader)?)
+        }
+    }
+
+    pub fn write_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut writer = BufWriter::new(File::create(path)?);
+        if is_ron(path) {
+            let ron = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            writer.write_all(ron.as_bytes())?;
+        } else {
+            serde_json::to_writer_pretty(&mut writer, self)?;
+        }
+        writer.flush()
+    }
+
+    // Unique morph names in mapping order
+    pub fn morph_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for mapping in &self.morphs {
+            if !names.contains(&mapping.morph) {
+                names.push(mapping.morph.clone());
+            }
+        }
+        names
+    }
+
+    /// Bakes the map into a track with a single blend shape node holding one
+    /// channel per morph, so any exporter writes retargeted weights.
+    pub fn retarget_track(&self, track: &Track, node: &str) -> Track {
+        let morph_names = self.morph_names();
+        let binding = self.bind(&track.rig, &morph_names);
+
+        let rig = Rig {
+            nodes: vec![RigNode {
+                name: node.to_string(),
+                node_type: NodeType::BlendShape,
+                channels: morph_names,
+            }],
+        };
+        let mut retargeted = Track::new(rig, track.frame_rate);
+        retargeted.sg_version = track.sg_version.clone();
+        retargeted.audio = track.audio.clone();
+        for frame in &track.frames {
+            retargeted.push(frame.time, vec![binding.evaluate(&frame.values)]);
+        }
+        retargeted
+    }
+
+    /// Mappings whose channel isn't in the rig or whose morph isn't in `morph_names`.
+    pub fn unresolved<'a>(
+        &'a self,
+        rig: &'a Rig,
+        morph_names: &'a [String],
+    ) -> impl Iterator<Item = &'a MorphMapping> {
+        self.morphs.iter().filter(|mapping| {
+            rig.find(&mapping.node, &mapping.channel).is_none()
+                || !morph_names.contains(&mapping.morph)
+        })
+    }
+
+    /// Resolves the mappings against a rig and a mesh's morph target names.
+    /// Mappings whose channel or morph can't be found are skipped.
+    pub fn bind(&self, rig: &Rig, morph_names: &[String]) -> MorphBinding {
+        let entries = self
+            .morphs
+            .iter()
+            .filter_map(|mapping| {
+                let morph_index = morph_names.iter().position(|name| *name == mapping.morph)?;
+                let (node_index, channel_index) = rig.find(&mapping.node, &mapping.channel)?;
+                Some(BoundMorph {
+                    morph_index,
+                    node_index,
+                    channel_index,
+                    mapping: mapping.clone(),
+                })
+            })
+            .collect();
+
+        MorphBinding {
+            morph_count: morph_names.len(),
+            mix: self.mix,
+            entries,
+        }
+    }
+
+    // Unique bone names in mapping order
+    pub fn bone_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for mapping in &self.bones {
+            if !names.contains(&mapping.bone) {
+                names.push(mapping.bone.clone());
+            }
+        }
+        names
+    }
+
+    /// Resolves the mappings onto one joint against a rig. `rest` is the
+    /// joint's current transform, used unless a mapping sets its own rest rotation.
+    pub fn bind_bone(&self, rig: &Rig, bone: &str, rest: (Quat, Vec3)) -> BoneBinding {
+        let mappings: Vec<&BoneMapping> = self
+            .bones
+            .iter()
+            .filter(|mapping| mapping.bone == bone)
+            .collect();
+        let entries = mappings
+            .iter()
+            .filter_map(|mapping| {
+                let (node_index, channel_index) = rig.find(&mapping.node, &mapping.channel)?;
+                Some(BoundBone {
+                    node_index,
+                    channel_index,
+                    mapping: (*mapping).clone(),
+                })
+            })
+            .collect();
+
+        BoneBinding {
+            rest_rotation: mappings
+                .iter()
+                .find_map(|mapping| mapping.rest_rotation())
+                .unwrap_or(rest.0),
+            rest_translation: rest.1,
+            entries,
+        }
+    }
+}
+
+fn is_ron(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "ron")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct BoundMorph {
+    morph_index: usize,
+    node_index: usize,
+    channel_index: usize,
+    mapping: MorphMapping,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MorphBinding {
+    morph_count: usize,
+    mix: MixMode,
+    entries: Vec<BoundMorph>,
+}
+
+impl MorphBinding {
+    pub fn morph_count(&self) -> usize {
+        self.morph_count
+    }
+
+    pub fn is_bound(&self, morph_index: usize) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.morph_index == morph_index)
+    }
+
+    /// Writes the bound morphs' weights, leaving the rest untouched.
+    pub fn apply(&self, values: &[Vec<f32>], weights: &mut [f32]) {
+        // (mixed value, contributions) per morph
+        let mut mixed: Vec<Option<(f32, usize)>> = vec![None; self.morph_count];
+        for entry in &self.entries {
+            let Some(value) = values
+                .get(entry.node_index)
+                .and_then(|node| node.get(entry.channel_index))
+            else {
+                continue;
+            };
+            let value = entry.mapping.response(*value);
+
+            let slot = &mut mixed[entry.morph_index];
+            *slot = Some(match (*slot, self.mix) {
+                (None, _) => (value, 1),
+                (Some((current, count)), MixMode::Max) => (current.max(value), count + 1),
+                (Some((current, count)), _) => (current + value, count + 1),
+            });
+        }
+
+        for (weight, mixed) in weights.iter_mut().zip(mixed) {
+            if let Some((value, count)) = mixed {
+                *weight = match self.mix {
+                    MixMode::Average => value / count as f32,
+                    _ => value,
+                };
+            }
+        }
+    }
+
+    pub fn evaluate(&self, values: &[Vec<f32>]) -> Vec<f32> {
+        let mut weights = vec![0.0; self.morph_count];
+        self.apply(values, &mut weights);
+        weights
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct BoundBone {
+    node_index: usize,
+    channel_index: usize,
+    mapping: BoneMapping,
+}
+
+/// A joint's rest pose and the channels moving it away from it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BoneBinding {
+    pub rest_rotation: Quat,
+    pub rest_translation: Vec3,
+    entries: Vec<BoundBone>,
+}
+
+impl BoneBinding {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The joint's local rotation and translation for a frame. Rotations are
+    /// applied in mapping order on top of the rest pose.
+    pub fn evaluate(&self, values: &[Vec<f32>]) -> (Quat, Vec3) {
+        let mut rotation = self.rest_rotation;
+        let mut translation = self.rest_translation;
+        for entry in &self.entries {
+            let Some(value) = values
+                .get(entry.node_index)
+                .and_then(|node| node.get(entry.channel_index))
+            else {
+                continue;
+            };
+            let Some(axis) = Vec3::from(entry.mapping.axis).try_normalize() else {
+                continue;
+            };
+            let amount = entry.mapping.amount(*value);
+            match entry.mapping.motion {
+                BoneMotion::Rotate => {
+                    rotation *= Quat::from_axis_angle(axis, amount.to_radians());
+                }
+                BoneMotion::Translate => {
+                    translation += self.rest_rotation * axis * amount;
+                }
+            }
+        }
+        (rotation, translation)
+    }
+}
+
+// Channels a preset reads that the character compiled into the binary doesn't have.
+// The built-in tables' channel names aren't taken from the character, so tests check
+// them with this; it needs the SG_Com runtime, so those tests are ignored by default.
+#[cfg(all(test, feature = "runtime"))]
+pub(crate) fn missing_on_shipped_character(map: &RetargetMap) -> Vec<&str> {
+    use crate::com::{self, SG_SampleRate, SG_SampleType};
+
+    let player = com::context()
+        .unwrap()
+        .add_player(
+            SG_SampleType::SG_SAMPLE_FLOAT32,
+            SG_SampleRate::SG_RATE_16KHZ,
+        )
+        .unwrap();
+    let rig = Rig::from_player(&player);
+    map.morphs
+        .iter()
+        .filter(|mapping| rig.find(&mapping.node, &mapping.channel).is_none())
+        .map(|mapping| mapping.channel.as_str())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn rig() -> Rig {
+        let node = |name: &str, channels: &[&str]| RigNode {
+            name: name.to_string(),
+            node_type: NodeType::BlendShape,
+            channels: channels.iter().map(|channel| channel.to_string()).collect(),
+        };
+        Rig {
+            nodes: vec![
+                node("blendBoard", &["jawOpen", "mouthSmile"]),
+                node("tongue", &["out"]),
+            ],
+        }
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sg-com-retarget-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn samples_curves() {
+        assert_eq!(Curve::Linear.sample(0.3), 0.3);
+        assert_eq!(Curve::SmoothStep.sample(0.5), 0.5);
+        assert_eq!(Curve::SmoothStep.sample(2.0), 1.0);
+        assert_eq!(Curve::EaseIn.sample(-0.5), -0.25);
+        assert_eq!(Curve::EaseOut.sample(0.5), 0.75);
+        assert_eq!(Curve::Power(2.0).sample(-0.5), -0.25);
+        assert_eq!(Curve::Power(0.5).sample(0.0), 0.0);
+
+        let points = Curve::Points(vec![[0.0, 0.0], [0.5, 1.0], [1.0, 0.5]]);
+        assert_eq!(points.sample(-1.0), 0.0);
+        assert_eq!(points.sample(0.25), 0.5);
+        assert_eq!(points.sample(0.75), 0.75);
+        assert_eq!(points.sample(2.0), 0.5);
+        assert_eq!(Curve::Points(Vec::new()).sample(0.3), 0.3);
+    }
+
+    #[test]
+    fn applies_gain_offset_then_clamp() {
+        let mut mapping = MorphMapping::new("blendBoard", "jawOpen", "Mouth");
+        mapping.gain = 2.0;
+        mapping.offset = -0.5;
+        assert_eq!(mapping.response(0.5), 0.5);
+        assert_eq!(mapping.response(1.0), 1.5);
+
+        mapping.clamp = Some([0.0, 1.0]);
+        assert_eq!(mapping.response(1.0), 1.0);
+        assert_eq!(mapping.response(0.0), 0.0);
+
+        mapping.curve = Curve::EaseIn;
+        assert_eq!(mapping.response(0.5), 0.0);
+    }
+
+    #[test]
+    fn mixes_mappings_onto_one_morph() {
+        let mut map = RetargetMap {
+            morphs: vec![
+                MorphMapping::new("blendBoard", "jawOpen", "Mouth"),
+                MorphMapping::new("blendBoard", "mouthSmile", "Mouth"),
+                MorphMapping::new("blendBoard", "mouthSmile", "Smile"),
+            ],
+            ..Default::default()
+        };
+        let morphs = names(&["Mouth", "Smile", "Untouched"]);
+        let values = [vec![0.5, 0.25], vec![1.0]];
+
+        let mut mixed = |mix| {
+            map.mix = mix;
+            let mut weights = vec![-1.0; 3];
+            map.bind(&rig(), &morphs).apply(&values, &mut weights);
+            weights
+        };
+        assert_eq!(mixed(MixMode::Add), [0.75, 0.25, -1.0]);
+        assert_eq!(mixed(MixMode::Max), [0.5, 0.25, -1.0]);
+        assert_eq!(mixed(MixMode::Average), [0.375, 0.25, -1.0]);
+    }
+
+    #[test]
+    fn binds_only_what_resolves() {
+        let map = RetargetMap {
+            morphs: vec![
+                MorphMapping::new("blendBoard", "jawOpen", "Mouth"),
+                MorphMapping::new("blendBoard", "missing", "Smile"),
+                MorphMapping::new("tongue", "out", "NotOnMesh"),
+            ],
+            ..Default::default()
+        };
+        let (rig, morphs) = (rig(), names(&["Mouth", "Smile"]));
+
+        let binding = map.bind(&rig, &morphs);
+        assert_eq!(binding.morph_count(), 2);
+        assert!(binding.is_bound(0));
+        assert!(!binding.is_bound(1));
+        // Missing nodes in a frame leave their morphs at rest
+        assert_eq!(binding.evaluate(&[vec![0.5, 0.25], vec![1.0]]), [0.5, 0.0]);
+        assert_eq!(binding.evaluate(&[]), [0.0, 0.0]);
+
+        let unresolved: Vec<_> = map
+            .unresolved(&rig, &morphs)
+            .map(|mapping| mapping.channel.as_str())
+            .collect();
+        assert_eq!(unresolved, ["missing", "out"]);
+    }
+
+    #[test]
+    fn binds_bones() {
+        let mut open = BoneMapping::new("blendBoard", "jawOpen", "Jaw", [0.0, 90.0]);
+        open.rest = Some([0.0, 0.0, 10.0]);
+        let mut slide = BoneMapping::new("tongue", "out", "Jaw", [0.0, 2.0]);
+        slide.motion = BoneMotion::Translate;
+        slide.axis = [0.0, 0.0, 4.0];
+        let map = RetargetMap {
+            bones: vec![
+                open,
+                slide,
+                BoneMapping::new("blendBoard", "jawOpen", "Other", [0.0, 1.0]),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(map.bone_names(), ["Jaw", "Other"]);
+
+        let rest = (Quat::IDENTITY, Vec3::new(0.0, 1.0, 0.0));
+        let binding = map.bind_bone(&rig(), "Jaw", rest);
+        assert_eq!(binding.len(), 2);
+
+        let rest_rotation = Quat::from_rotation_z(10f32.to_radians());
+        assert!(binding.rest_rotation.abs_diff_eq(rest_rotation, 1e-5));
+        let (rotation, translation) = binding.evaluate(&[vec![0.5, 0.0], vec![1.5]]);
+        let expected = rest_rotation * Quat::from_rotation_x(45f32.to_radians());
+        assert!(rotation.abs_diff_eq(expected, 1e-5));
+        // Channel values are clamped to 0..1, and translations follow the rest rotation
+        let slid = rest.1 + rest_rotation * Vec3::Z * 2.0;
+        assert!(translation.abs_diff_eq(slid, 1e-5));
+
+        assert!(map.bind_bone(&rig(), "Missing", rest).is_empty());
+    }
+
+    #[test]
+    fn retargets_tracks_onto_morph_channels() {
+        let mut map = RetargetMap::pose_suffix(&rig());
+        map.morphs[1].gain = 2.0;
+        let mut track = Track::new(rig(), 30.0);
+        track.audio = Some("take.wav".to_string());
+        track.push(0.0, vec![vec![0.5, 0.25], vec![1.0]]);
+
+        let retargeted = map.retarget_track(&track, "morphs");
+        assert_eq!(retargeted.rig.nodes.len(), 1);
+        assert_eq!(retargeted.rig.nodes[0].name, "morphs");
+        assert_eq!(
+            retargeted.rig.nodes[0].channels,
+            ["jawOpen_pose", "mouthSmile_pose"]
+        );
+        assert_eq!(retargeted.frames[0].values, [[0.5, 0.5]]);
+        assert_eq!(retargeted.frame_rate, 30.0);
+        assert_eq!(retargeted.audio, track.audio);
+    }
+
+    #[test]
+    fn reads_ron_and_json() {
+        let mut mapping = MorphMapping::new("blendBoard", "jawOpen", "Mouth");
+        mapping.gain = 1.5;
+        mapping.clamp = Some([0.0, 1.0]);
+        mapping.curve = Curve::Power(2.0);
+        let map = RetargetMap {
+            morphs: vec![mapping],
+            mix: MixMode::Max,
+            bones: vec![BoneMapping::new(
+                "blendBoard",
+                "jawOpen",
+                "Jaw",
+                [0.0, 20.0],
+            )],
+        };
+
+        for name in ["map.ron", "map.json"] {
+            let path = temp_path(name);
+            map.write_file(&path).unwrap();
+            let read = RetargetMap::read_file(&path);
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(read.unwrap(), map);
+        }
+
+        let path = temp_path("written.ron");
+        std::fs::write(
+            &path,
+            r#"(morphs: [(node: "blendBoard", channel: "jawOpen", morph: "Mouth", curve: smooth_step)])"#,
+        )
+        .unwrap();
+        let read = RetargetMap::read_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        let read = read.unwrap();
+        assert_eq!(read.morphs[0].curve, Curve::SmoothStep);
+        assert_eq!(read.morphs[0].gain, 1.0);
+        assert_eq!(read.mix, MixMode::Add);
+    }
+
+    #[test]
+    fn rejects_non_positive_power_exponents() {
+        let json = |exponent: &str| {
+            format!(
+                r#"{{"morphs": [{{"node": "blendBoard", "channel": "jawOpen", "morph": "Mouth", "curve": {{"power": {exponent}}}}}]}}"#
+            )
+        };
+        assert!(serde_json::from_str::<RetargetMap>(&json("0.5")).is_ok());
+        assert!(serde_json::from_str::<RetargetMap>(&json("0")).is_err());
+        assert!(serde_json::from_str::<RetargetMap>(&json("-1")).is_err());
+
+        let path = temp_path("negative.ron");
+        std::fs::write(
+            &path,
+            r#"(morphs: [(node: "blendBoard", channel: "jawOpen", morph: "Mouth", curve: power(-2.0))])"#,
+        )
+        .unwrap();
+        let read = RetargetMap::read_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn names_presets() {
+        assert_eq!(Preset::from_name("ARKit"), Some(Preset::Arkit));
+        assert_eq!(Preset::from_name("pose"), Some(Preset::PoseSuffix));
+        assert_eq!(Preset::from_name("vrm"), Some(Preset::Vrm));
+        assert_eq!(Preset::from_name("map.json"), None);
+        assert_eq!(
+            Preset::PoseSuffix.map(&rig()),
+            RetargetMap::pose_suffix(&rig())
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use super::{MixMode, MorphMapping, RetargetMap, POSE_NODE};
+
+// `blendBoard` channel, VRM 0.x blend shape, VRM 1.0 expression. The channel names
+// are placeholders following ARKit naming, not read from the shipped character;
+// `reads_channels_of_the_shipped_character` checks them where SG_Com is available.
+const VRM_TABLE: &[(&str, &str, &str)] = &[
+    ("jawOpen", "A", "aa"),
+    ("mouthStretch", "I", "ih"),
+    ("mouthFunnel", "U", "ou"),
+    ("mouthSmile", "E", "ee"),
+    ("mouthPucker", "O", "oh"),
+    ("eyeBlink", "Blink", "blink"),
+    ("eyeBlinkLeft", "Blink_L", "blinkLeft"),
+    ("eyeBlinkRight", "Blink_R", "blinkRight"),
+];
+
+/// VRM 0.x blend shape names, as VMC receivers expect them.
+pub fn preset() -> RetargetMap {
+    RetargetMap {
+        morphs: VRM_TABLE
+            .iter()
+            .map(|(channel, name, _)| MorphMapping::new(POSE_NODE, *channel, *name))
+            .collect(),
+        mix: MixMode::default(),
+        bones: Vec::new(),
+    }
+}
+
+/// VRM 1.0 expression names (`aa`, `ih`, `ou`, `ee`, `oh`, `blink`, ...), used
+/// for both model versions once their presets are normalized.
+pub fn expressions() -> RetargetMap {
+    RetargetMap {
+        morphs: VRM_TABLE
+            .iter()
+            .map(|(channel, _, name)| MorphMapping::new(POSE_NODE, *channel, *name))
+            .collect(),
+        mix: MixMode::default(),
+        bones: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn covers_both_vrm_versions() {
+        let names = |map: RetargetMap| map.morph_names();
+        assert_eq!(
+            names(preset()),
+            ["A", "I", "U", "E", "O", "Blink", "Blink_L", "Blink_R"]
+        );
+        assert_eq!(
+            names(expressions()),
+            [
+                "aa",
+                "ih",
+                "ou",
+                "ee",
+                "oh",
+                "blink",
+                "blinkLeft",
+                "blinkRight"
+            ]
+        );
+    }
+
+    #[cfg(feature = "runtime")]
+    #[test]
+    #[ignore = "needs the SG_Com runtime"]
+    fn reads_channels_of_the_shipped_character() {
+        assert_eq!(
+            crate::retarget::missing_on_shipped_character(&preset()),
+            Vec::<&str>::new()
+        );
+    }
+}
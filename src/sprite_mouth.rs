@@ -0,0 +1,220 @@
+use crate::{facial_anim::FacialAnim, viseme::Visemes};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Swaps mouth sprites (`TextureAtlas` indices) for entities with a `SpriteMouth`.
+pub struct SpriteMouthPlugin;
+
+impl Plugin for SpriteMouthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, drive_sprite_mouths);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouthInput {
+    Channel {
+        node: String,
+        channel: String,
+    },
+    /// A viseme weight from the `Visemes` resource, e.g. `"aa"`
+    Viseme(String),
+}
+
+/// Shows atlas frame `index` while `input` is at least `threshold`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MouthRule {
+    pub input: MouthInput,
+    pub threshold: f32,
+    pub index: usize,
+}
+
+/// Picks the rule with the strongest input. A new frame is only shown once the
+/// current one has been held for `hold` seconds and beats it by `hysteresis`.
+#[derive(Component, Debug, Clone)]
+pub struct SpriteMouth {
+    pub rules: Vec<MouthRule>,
+    /// Shown when no rule passes its threshold
+    pub rest_index: usize,
+    pub hold: f32,
+    pub hysteresis: f32,
+    current: usize,
+    changed_at: f32,
+}
+
+impl SpriteMouth {
+    pub fn new(rules: Vec<MouthRule>, rest_index: usize) -> Self {
+        Self {
+            rules,
+            rest_index,
+            hold: 0.08,
+            hysteresis: 0.1,
+            current: rest_index,
+            changed_at: 0.0,
+        }
+    }
+
+    /// One frame per viseme, e.g. `[("PP", 1), ("aa", 2), ("ou", 3)]`.
+    pub fn from_visemes<'a>(
+        frames: impl IntoIterator<Item = (&'a str, usize)>,
+        rest_index: usize,
+    ) -> Self {
+        let rules = frames
+            .into_iter()
+            .map(|(viseme, index)| MouthRule {
+                input: MouthInput::Viseme(viseme.to_string()),
+                threshold: 0.2,
+                index,
+            })
+            .collect();
+        Self::new(rules, rest_index)
+    }
+
+    pub fn with_hold(mut self, hold: f32) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    pub fn with_hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    fn update(&mut self, values: &[f32], now: f32) -> usize {
+        let score = |index: usize| {
+            self.rules
+                .iter()
+                .zip(values)
+                .filter(|(rule, value)| rule.index == index && **value >= rule.threshold)
+                .map(|(_, value)| *value)
+                .fold(0.0, f32::max)
+        };
+
+        let candidate = self
+            .rules
+            .iter()
+            .zip(values)
+            .filter(|(rule, value)| **value >= rule.threshold)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(rule, value)| (rule.index, *value));
+
+        let held = now - self.changed_at >= self.hold;
+        let next = match candidate {
+            Some((index, value)) if value >= score(self.current) + self.hysteresis => index,
+            Some(_) => self.current,
+            None => self.rest_index,
+        };
+        if next != self.current && held {
+            self.current = next;
+            self.changed_at = now;
+        }
+        self.current
+    }
+}
+
+fn drive_sprite_mouths(
+    anim: Res<FacialAnim>,
+    visemes: Option<Res<Visemes>>,
+    time: Res<Time>,
+    mut mouths: Query<(&mut SpriteMouth, &mut Sprite)>,
+) {
+    let Some(processed_data) = &anim.processed_data else {
+        return;
+    };
+
+    for (mut mouth, mut sprite) in &mut mouths {
+        let values: Vec<f32> = mouth
+            .rules
+            .iter()
+            .map(|rule| match &rule.input {
+                MouthInput::Channel { node, channel } => anim
+                    .rig
+                    .find(node, channel)
+                    .and_then(|(node, channel)| processed_data.get(node)?.get(channel).copied())
+                    .unwrap_or_default(),
+                MouthInput::Viseme(viseme) => visemes
+                    .as_ref()
+                    .and_then(|visemes| {
+                        let index = visemes.set.names().iter().position(|name| name == viseme)?;
+                        visemes.weights.get(index).copied()
+                    })
+                    .unwrap_or_default(),
+            })
+            .collect();
+
+        let index = mouth.update(&values, time.elapsed_secs());
+        // Only touch the sprite when the frame actually changes
+        let changed = sprite
+            .texture_atlas
+            .as_ref()
+            .is_some_and(|atlas| atlas.index != index);
+        if changed {
+            if let Some(atlas) = &mut sprite.texture_atlas {
+                atlas.index = index;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frame 1 for "aa" from 0.2, frame 2 for "ou" from 0.5, frame 0 at rest
+    fn mouth() -> SpriteMouth {
+        let rule = |viseme: &str, threshold, index| MouthRule {
+            input: MouthInput::Viseme(viseme.to_string()),
+            threshold,
+            index,
+        };
+        SpriteMouth::new(vec![rule("aa", 0.2, 1), rule("ou", 0.5, 2)], 0)
+    }
+
+    #[test]
+    fn shows_the_strongest_rule_past_its_threshold() {
+        let mut mouth = mouth().with_hold(0.0).with_hysteresis(0.0);
+        assert_eq!(mouth.update(&[0.1, 0.4], 1.0), 0);
+        assert_eq!(mouth.update(&[0.3, 0.4], 2.0), 1);
+        assert_eq!(mouth.update(&[0.3, 0.6], 3.0), 2);
+        assert_eq!(mouth.update(&[0.7, 0.6], 4.0), 1);
+        assert_eq!(mouth.current(), 1);
+    }
+
+    #[test]
+    fn hysteresis_keeps_the_current_frame() {
+        let mut mouth = mouth().with_hold(0.0).with_hysteresis(0.25);
+        assert_eq!(mouth.update(&[0.5, 0.0], 1.0), 1);
+        assert_eq!(mouth.update(&[0.5, 0.625], 2.0), 1);
+        assert_eq!(mouth.update(&[0.5, 0.75], 3.0), 2);
+        // The current frame's own input can always rise
+        assert_eq!(mouth.update(&[0.5, 1.0], 4.0), 2);
+    }
+
+    #[test]
+    fn holds_a_frame_before_changing_it() {
+        let mut mouth = mouth().with_hold(0.5).with_hysteresis(0.0);
+        // Nothing was shown before the first hold
+        assert_eq!(mouth.update(&[0.5, 0.0], 0.25), 0);
+        assert_eq!(mouth.update(&[0.5, 0.0], 1.0), 1);
+        assert_eq!(mouth.update(&[0.0, 1.0], 1.25), 1);
+        assert_eq!(mouth.update(&[0.0, 0.0], 1.25), 1);
+        assert_eq!(mouth.update(&[0.0, 1.0], 1.5), 2);
+    }
+
+    #[test]
+    fn falls_back_to_the_rest_frame() {
+        let mut mouth = mouth().with_hold(0.25);
+        assert_eq!(mouth.update(&[0.5, 0.0], 1.0), 1);
+        assert_eq!(mouth.update(&[0.1, 0.1], 1.0), 1);
+        assert_eq!(mouth.update(&[0.1, 0.1], 1.25), 0);
+
+        assert_eq!(mouth.update(&[0.5, 0.0], 2.0), 1);
+        // Rules without a value never pass
+        assert_eq!(mouth.update(&[], 3.0), 0);
+    }
+}
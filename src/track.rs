@@ -0,0 +1,157 @@
+#[cfg(feature = "runtime")]
+use crate::com::{Player, SG_AnimationNodeType};
+use bevy::{asset::Asset, reflect::TypePath};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const TRACK_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeType {
+    Joint,
+    BlendShape,
+    Control,
+}
+
+#[cfg(feature = "runtime")]
+impl From<SG_AnimationNodeType> for NodeType {
+    fn from(node_type: SG_AnimationNodeType) -> Self {
+        match node_type {
+            SG_AnimationNodeType::SG_NODE_JOINT => Self::Joint,
+            SG_AnimationNodeType::SG_NODE_BLEND_SHAPE => Self::BlendShape,
+            SG_AnimationNodeType::SG_NODE_CONTROL => Self::Control,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RigNode {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub node_type: NodeType,
+    pub channels: Vec<String>,
+}
+
+/// Layout of the values returned by `Player::process`, one entry per output node.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Rig {
+    pub nodes: Vec<RigNode>,
+}
+
+impl Rig {
+    #[cfg(feature = "runtime")]
+    pub fn from_player(player: &Player) -> Self {
+        Self {
+            // Player::process skips nodes without channels, so the rig does too
+            nodes: player
+                .animation_info()
+                .iter()
+                .filter(|node| node.channel_count() != 0)
+                .map(|node| RigNode {
+                    name: node.name(),
+                    node_type: node.node_type().into(),
+                    channels: node.channel_names().to_vec(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.nodes.iter().map(|node| node.channels.len()).sum()
+    }
+
+    pub fn find(&self, node: &str, channel: &str) -> Option<(usize, usize)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .find(|(_, rig_node)| rig_node.name == node)
+            .and_then(|(node_index, rig_node)| {
+                rig_node
+                    .channels
+                    .iter()
+                    .position(|rig_channel| rig_channel == channel)
+                    .map(|channel_index| (node_index, channel_index))
+            })
+    }
+
+    /// The value for every channel in output order, `None` where SG_Com
+    /// returned no data for the channel's node.
+    pub fn aligned_values<'a>(
+        &'a self,
+        values: &'a [Vec<f32>],
+    ) -> impl Iterator<Item = Option<f32>> + 'a {
+        self.nodes
+            .iter()
+            .enumerate()
+            .flat_map(move |(index, node)| {
+                let node_values = values.get(index).map(Vec::as_slice).unwrap_or_default();
+                (0..node.channels.len()).map(move |channel| node_values.get(channel).copied())
+            })
+    }
+
+    // (node, channel) pairs in output order
+    pub fn channel_paths(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.nodes.iter().flat_map(|node| {
+            node.channels
+                .iter()
+                .map(move |channel| (node.name.as_str(), channel.as_str()))
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    /// Seconds since the start of the track
+    pub time: f32,
+    #[serde(deserialize_with = "deserialize_values")]
+    pub values: Vec<Vec<f32>>,
+}
+
+// JSON has no NaN, so serde_json writes missing values as `null`; read them back as NaN
+fn deserialize_values<'de, D>(deserializer: D) -> Result<Vec<Vec<f32>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let values = Vec::<Vec<Option<f32>>>::deserialize(deserializer)?;
+    Ok(values
+        .into_iter()
+        .map(|node| node.into_iter().map(|v| v.unwrap_or(f32::NAN)).collect())
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Asset, TypePath)]
+pub struct Track {
+    pub format_version: u32,
+    pub sg_version: Option<String>,
+    pub frame_rate: f32,
+    /// Audio file the track was made from, relative to the track file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<String>,
+    pub rig: Rig,
+    pub frames: Vec<Frame>,
+}
+
+impl Track {
+    pub fn new(rig: Rig, frame_rate: f32) -> Self {
+        Self {
+            format_version: TRACK_FORMAT_VERSION,
+            sg_version: None,
+            frame_rate,
+            audio: None,
+            rig,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.frames
+            .last()
+            .map(|frame| Duration::from_secs_f32(frame.time))
+            .unwrap_or_default()
+    }
+
+    pub fn push(&mut self, time: f32, values: Vec<Vec<f32>>) {
+        self.frames.push(Frame { time, values });
+    }
+}
@@ -0,0 +1,316 @@
+use crate::{
+    facial_anim::{process_data, FacialAnim},
+    retarget::{MixMode, MorphBinding, MorphMapping, RetargetMap, POSE_NODE},
+    track::{Rig, Track},
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Meta/Oculus visemes; index 0 is silence.
+pub const OCULUS_VISEMES: [&str; 15] = [
+    "sil", "PP", "FF", "TH", "DD", "kk", "CH", "SS", "nn", "RR", "aa", "E", "ih", "oh", "ou",
+];
+
+// SAPI viseme id for each Oculus viseme
+const OCULUS_TO_SAPI: [u32; 15] = [0, 21, 18, 17, 19, 20, 16, 15, 19, 13, 2, 4, 6, 8, 7];
+
+/// Microsoft SAPI viseme ids 0-21, named after their first phoneme.
+pub const SAPI_VISEMES: [&str; 22] = [
+    "silence", "ae", "aa", "ao", "ey", "er", "y", "w", "ow", "aw", "oy", "ay", "h", "r", "l", "s",
+    "sh", "th", "f", "d", "k", "p",
+];
+
+// `blendBoard` channel -> Oculus viseme, approximating each viseme by the
+// control that dominates its mouth shape. The channel names are placeholders
+// following ARKit naming, not read from the shipped character;
+// `reads_channels_of_the_shipped_character` checks them where SG_Com is available.
+const VISEME_TABLE: &[(&str, &str)] = &[
+    ("mouthClose", "PP"),
+    ("mouthPress", "PP"),
+    ("mouthRollLower", "FF"),
+    ("tongueOut", "TH"),
+    ("tongueUp", "DD"),
+    ("tongueBack", "kk"),
+    ("mouthShrugUpper", "CH"),
+    ("mouthStretch", "SS"),
+    ("mouthRollUpper", "RR"),
+    ("jawOpen", "aa"),
+    ("mouthSmile", "E"),
+    ("mouthDimple", "ih"),
+    ("mouthFunnel", "oh"),
+    ("mouthPucker", "ou"),
+];
+
+// Below this every viseme counts as silence
+const SILENCE_THRESHOLD: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VisemeSet {
+    #[default]
+    Oculus,
+    Sapi,
+}
+
+impl VisemeSet {
+    pub fn names(self) -> &'static [&'static str] {
+        match self {
+            VisemeSet::Oculus => &OCULUS_VISEMES,
+            VisemeSet::Sapi => &SAPI_VISEMES,
+        }
+    }
+
+    /// Converts Oculus viseme weights, keeping the strongest where several map to one id.
+    fn convert(self, oculus: &[f32]) -> Vec<f32> {
+        match self {
+            VisemeSet::Oculus => oculus.to_vec(),
+            VisemeSet::Sapi => {
+                let mut weights = vec![0.0; SAPI_VISEMES.len()];
+                for (oculus_index, weight) in oculus.iter().enumerate() {
+                    let sapi = &mut weights[OCULUS_TO_SAPI[oculus_index] as usize];
+                    *sapi = weight.max(*sapi);
+                }
+                weights
+            }
+        }
+    }
+}
+
+/// Default channel to Oculus viseme map; override it to match a character's control board.
+pub fn viseme_map() -> RetargetMap {
+    RetargetMap {
+        morphs: VISEME_TABLE
+            .iter()
+            .map(|(channel, viseme)| {
+                let mut mapping = MorphMapping::new(POSE_NODE, *channel, *viseme);
+                mapping.clamp = Some([0.0, 1.0]);
+                mapping
+            })
+            .collect(),
+        mix: MixMode::Max,
+        bones: Vec::new(),
+    }
+}
+
+/// Turns SG frames into weighted visemes and picks the dominant one.
+#[derive(Debug, Clone)]
+pub struct VisemeClassifier {
+    pub set: VisemeSet,
+    binding: MorphBinding,
+}
+
+impl VisemeClassifier {
+    /// `map` targets Oculus viseme names, see `viseme_map`.
+    pub fn new(set: VisemeSet, map: &RetargetMap, rig: &Rig) -> Self {
+        let names: Vec<String> = OCULUS_VISEMES.iter().map(|name| name.to_string()).collect();
+        Self {
+            set,
+            binding: map.bind(rig, &names),
+        }
+    }
+
+    /// Returns the weight of every viseme in the set, and the dominant viseme's index.
+    pub fn classify(&self, values: &[Vec<f32>]) -> (Vec<f32>, usize) {
+        let mut oculus = self.binding.evaluate(values);
+        let loudest = oculus[1..].iter().copied().fold(0.0, f32::max);
+        oculus[0] = (1.0 - loudest).clamp(0.0, 1.0);
+
+        let weights = self.set.convert(&oculus);
+        let dominant = if loudest < SILENCE_THRESHOLD {
+            0
+        } else {
+            weights
+                .iter()
+                .enumerate()
+                .skip(1)
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(index, _)| index)
+                .unwrap_or(0)
+        };
+        (weights, dominant)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VisemeEvent {
+    pub id: u32,
+    pub viseme: String,
+    pub start: f32,
+    pub end: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VisemeFrame {
+    pub time: f32,
+    pub dominant: u32,
+    pub weights: Vec<f32>,
+}
+
+/// Dominant visemes with their timing, plus the full weights of every frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VisemeTrack {
+    pub set: VisemeSet,
+    pub visemes: Vec<String>,
+    pub events: Vec<VisemeEvent>,
+    pub frames: Vec<VisemeFrame>,
+}
+
+impl VisemeTrack {
+    pub fn from_track(track: &Track, classifier: &VisemeClassifier) -> Self {
+        let names = classifier.set.names();
+        let frames: Vec<VisemeFrame> = track
+            .frames
+            .iter()
+            .map(|frame| {
+                let (weights, dominant) = classifier.classify(&frame.values);
+                VisemeFrame {
+                    time: frame.time,
+                    dominant: dominant as u32,
+                    weights,
+                }
+            })
+            .collect();
+
+        // The last frame lasts one frame period, to the end of the track
+        let frame_period = if track.frame_rate > 0.0 {
+            1.0 / track.frame_rate
+        } else {
+            0.0
+        };
+        let mut events: Vec<VisemeEvent> = Vec::new();
+        for (index, frame) in frames.iter().enumerate() {
+            let end = frames
+                .get(index + 1)
+                .map_or(frame.time + frame_period, |next| next.time);
+            match events.last_mut() {
+                Some(event) if event.id == frame.dominant => event.end = end,
+                _ => events.push(VisemeEvent {
+                    id: frame.dominant,
+                    viseme: names[frame.dominant as usize].to_string(),
+                    start: frame.time,
+                    end,
+                }),
+            }
+        }
+
+        Self {
+            set: classifier.set,
+            visemes: names.iter().map(|name| name.to_string()).collect(),
+            events,
+            frames,
+        }
+    }
+}
+
+/// Classifies live frames into the `Visemes` resource.
+pub struct VisemePlugin {
+    pub set: VisemeSet,
+    /// Defaults to `viseme_map`
+    pub map: Option<RetargetMap>,
+}
+
+impl Plugin for VisemePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Visemes {
+            set: self.set,
+            map: self.map.clone().unwrap_or_else(viseme_map),
+            classifier: None,
+            generation: 0,
+            weights: Vec::new(),
+            dominant: 0,
+            since: 0.0,
+        });
+        app.add_systems(PreUpdate, classify_visemes.after(process_data));
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct Visemes {
+    pub set: VisemeSet,
+    map: RetargetMap,
+    classifier: Option<VisemeClassifier>,
+    // The animator's retarget generation `classifier` was built for
+    generation: u32,
+    pub weights: Vec<f32>,
+    pub dominant: usize,
+    /// Seconds since startup when `dominant` last changed
+    pub since: f32,
+}
+
+impl Visemes {
+    pub fn dominant_name(&self) -> &'static str {
+        self.set.names()[self.dominant]
+    }
+}
+
+fn classify_visemes(mut visemes: ResMut<Visemes>, anim: Res<FacialAnim>, time: Res<Time>) {
+    let Some(processed_data) = &anim.processed_data else {
+        return;
+    };
+
+    let visemes = &mut *visemes;
+    if visemes
+        .classifier
+        .as_ref()
+        .is_none_or(|classifier| classifier.set != visemes.set)
+        || visemes.generation != anim.retarget_generation()
+    {
+        visemes.classifier = Some(VisemeClassifier::new(visemes.set, &visemes.map, &anim.rig));
+        visemes.generation = anim.retarget_generation();
+    }
+    let Some(classifier) = &visemes.classifier else {
+        return;
+    };
+    let (weights, dominant) = classifier.classify(processed_data);
+
+    if dominant != visemes.dominant {
+        visemes.dominant = dominant;
+        visemes.since = time.elapsed_secs();
+    }
+    visemes.weights = weights;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::{NodeType, RigNode};
+
+    #[test]
+    fn events_cover_the_whole_track() {
+        let rig = Rig {
+            nodes: vec![RigNode {
+                name: POSE_NODE.to_string(),
+                node_type: NodeType::Control,
+                channels: vec!["jawOpen".to_string(), "mouthPucker".to_string()],
+            }],
+        };
+        let mut track = Track::new(rig.clone(), 10.0);
+        track.push(0.0, vec![vec![0.0, 0.0]]);
+        track.push(0.1, vec![vec![0.8, 0.0]]);
+        track.push(0.2, vec![vec![0.9, 0.0]]);
+        track.push(0.3, vec![vec![0.0, 0.7]]);
+
+        let classifier = VisemeClassifier::new(VisemeSet::Oculus, &viseme_map(), &rig);
+        let visemes = VisemeTrack::from_track(&track, &classifier);
+
+        let events: Vec<_> = visemes
+            .events
+            .iter()
+            .map(|event| (event.viseme.as_str(), event.start, event.end))
+            .collect();
+        assert_eq!(
+            events,
+            [("sil", 0.0, 0.1), ("aa", 0.1, 0.3), ("ou", 0.3, 0.4)]
+        );
+    }
+
+    #[cfg(feature = "runtime")]
+    #[test]
+    #[ignore = "needs the SG_Com runtime"]
+    fn reads_channels_of_the_shipped_character() {
+        assert_eq!(
+            crate::retarget::missing_on_shipped_character(&viseme_map()),
+            Vec::<&str>::new()
+        );
+    }
+}
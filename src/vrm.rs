@@ -0,0 +1,528 @@
+use crate::{
+    export::gltf::GltfDocument,
+    facial_anim::FacialAnim,
+    retarget::{self, MorphBinding, RetargetMap},
+};
+use bevy::{
+    asset::{
+        io::{Reader, VecReader},
+        AssetLoader, LoadContext,
+    },
+    gltf::{Gltf, GltfError, GltfLoader, GltfLoaderSettings},
+    image::CompressedImageFormats,
+    log,
+    prelude::*,
+    render::renderer::RenderDevice,
+    utils::HashMap,
+};
+use serde_json::Value;
+use std::f32::consts::PI;
+
+/// Label of the `VrmModel` a `.vrm` file carries, as in `character.vrm#VrmModel`.
+pub const VRM_MODEL_LABEL: &str = "VrmModel";
+
+// SG mood -> VRM emotion expression
+const MOOD_EXPRESSIONS: &[(&str, &[&str])] = &[
+    ("happy", &["happy", "joy", "joyful", "excited"]),
+    ("angry", &["angry", "anger", "mad"]),
+    ("sad", &["sad", "sadness", "sorrow"]),
+    ("relaxed", &["relaxed", "calm", "content", "fun"]),
+    ("surprised", &["surprised", "surprise", "shock"]),
+];
+
+/// Loads `.vrm` models and drives their expressions from SG output and mood.
+pub struct VrmPlugin;
+
+impl Plugin for VrmPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<VrmModel>().add_systems(
+            Update,
+            (
+                apply_vrm_scenes,
+                drive_expressions.run_if(resource_exists::<VrmModel>),
+            )
+                .chain(),
+        );
+    }
+
+    // Same as `GltfPlugin`, which needs the render device to pick texture formats
+    fn finish(&self, app: &mut App) {
+        let supported_compressed_formats = match app.world().get_resource::<RenderDevice>() {
+            Some(render_device) => CompressedImageFormats::from_features(render_device.features()),
+            None => CompressedImageFormats::NONE,
+        };
+        app.register_asset_loader(VrmLoader(GltfLoader {
+            supported_compressed_formats,
+            custom_vertex_attributes: HashMap::default(),
+        }));
+    }
+}
+
+/// VRM files are GLB files with extra extensions, so they load as regular glTF,
+/// plus a `VrmModel` read from the same bytes.
+struct VrmLoader(GltfLoader);
+
+impl AssetLoader for VrmLoader {
+    type Asset = Gltf;
+    type Settings = GltfLoaderSettings;
+    type Error = GltfError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        settings: &GltfLoaderSettings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Gltf, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        // Malformed files are left for the glTF loader to report
+        if let Some(vrm) = GltfDocument::from_slice(&bytes)
+            .ok()
+            .and_then(|document| VrmModel::from_document(&document))
+        {
+            load_context.add_labeled_asset(VRM_MODEL_LABEL.to_string(), vrm);
+        }
+        self.0
+            .load(&mut VecReader::new(bytes), settings, load_context)
+            .await
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vrm"]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VrmVersion {
+    /// The `VRM` extension; models face -Z
+    V0,
+    /// The `VRMC_vrm` extension; models face +Z
+    V1,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MorphTargetBind {
+    pub node: usize,
+    pub index: usize,
+    pub weight: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    /// VRM 1.0 preset name (`aa`, `happy`, ...) or the custom expression name
+    pub name: String,
+    pub binds: Vec<MorphTargetBind>,
+}
+
+#[derive(Resource, Asset, TypePath, Debug, Clone)]
+pub struct VrmModel {
+    pub version: VrmVersion,
+    pub expressions: Vec<Expression>,
+    /// glTF node names, which become the `Name` of the spawned entities
+    pub node_names: Vec<String>,
+    /// SG channels to expression names, `retarget::vrm::expressions` by default
+    pub retarget: RetargetMap,
+}
+
+impl VrmModel {
+    /// Returns `None` for glTF files without a VRM extension.
+    pub fn from_document(document: &GltfDocument) -> Option<Self> {
+        let json = &document.json;
+        let extensions = json.get("extensions")?;
+        let (version, expressions) = if let Some(vrm) = extensions.get("VRMC_vrm") {
+            (VrmVersion::V1, expressions_v1(vrm))
+        } else if let Some(vrm) = extensions.get("VRM") {
+            (VrmVersion::V0, expressions_v0(vrm, json))
+        } else {
+            return None;
+        };
+
+        let node_names = json["nodes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, node)| match node["name"].as_str() {
+                Some(name) => name.to_string(),
+                None => format!("GltfNode{index}"),
+            })
+            .collect();
+
+        Some(Self {
+            version,
+            expressions,
+            node_names,
+            retarget: retarget::vrm::expressions(),
+        })
+    }
+
+    pub fn expression_names(&self) -> Vec<String> {
+        self.expressions
+            .iter()
+            .map(|expression| expression.name.clone())
+            .collect()
+    }
+
+    pub fn expression_index(&self, name: &str) -> Option<usize> {
+        self.expressions
+            .iter()
+            .position(|expression| expression.name == name)
+    }
+
+    // (expression index, morph index, weight) for every bind on the named node
+    fn node_binds(&self) -> HashMap<String, Vec<(usize, usize, f32)>> {
+        let mut binds: HashMap<String, Vec<_>> = HashMap::default();
+        for (expression_index, expression) in self.expressions.iter().enumerate() {
+            for bind in &expression.binds {
+                if let Some(node) = self.node_names.get(bind.node) {
+                    binds.entry(node.clone()).or_default().push((
+                        expression_index,
+                        bind.index,
+                        bind.weight,
+                    ));
+                }
+            }
+        }
+        binds
+    }
+}
+
+fn expressions_v1(vrm: &Value) -> Vec<Expression> {
+    let expressions = &vrm["expressions"];
+    ["preset", "custom"]
+        .iter()
+        .flat_map(|group| expressions[group].as_object().into_iter().flatten())
+        .map(|(name, expression)| Expression {
+            name: name.clone(),
+            binds: expression["morphTargetBinds"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|bind| {
+                    Some(MorphTargetBind {
+                        node: bind["node"].as_u64()? as usize,
+                        index: bind["index"].as_u64()? as usize,
+                        weight: bind["weight"].as_f64().unwrap_or(1.0) as f32,
+                    })
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+// 0.x binds target meshes with weights out of 100
+fn expressions_v0(vrm: &Value, json: &Value) -> Vec<Expression> {
+    let nodes = json["nodes"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let mesh_nodes = |mesh: u64| {
+        nodes
+            .iter()
+            .enumerate()
+            .filter(move |(_, node)| node["mesh"].as_u64() == Some(mesh))
+            .map(|(index, _)| index)
+    };
+
+    vrm["blendShapeMaster"]["blendShapeGroups"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|group| {
+            let name = group["presetName"]
+                .as_str()
+                .and_then(preset_name_v0)
+                .or_else(|| group["name"].as_str())
+                .unwrap_or_default()
+                .to_string();
+            let binds = group["binds"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|bind| {
+                    let mesh = bind["mesh"].as_u64()?;
+                    let index = bind["index"].as_u64()? as usize;
+                    let weight = bind["weight"].as_f64().unwrap_or(100.0) as f32 / 100.0;
+                    Some(mesh_nodes(mesh).map(move |node| MorphTargetBind {
+                        node,
+                        index,
+                        weight,
+                    }))
+                })
+                .flatten()
+                .collect();
+            Expression { name, binds }
+        })
+        .collect()
+}
+
+/// Renames a VRM 0.x blend shape preset to its VRM 1.0 expression.
+pub fn preset_name_v0(preset: &str) -> Option<&'static str> {
+    let name = match preset.to_ascii_lowercase().as_str() {
+        "neutral" => "neutral",
+        "a" => "aa",
+        "i" => "ih",
+        "u" => "ou",
+        "e" => "ee",
+        "o" => "oh",
+        "blink" => "blink",
+        "blink_l" => "blinkLeft",
+        "blink_r" => "blinkRight",
+        "joy" => "happy",
+        "angry" => "angry",
+        "sorrow" => "sad",
+        "fun" => "relaxed",
+        "lookup" => "lookUp",
+        "lookdown" => "lookDown",
+        "lookleft" => "lookLeft",
+        "lookright" => "lookRight",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// The emotion expression shown for an SG mood, if any.
+pub fn mood_expression(mood: &str) -> Option<&'static str> {
+    let mood = mood.to_ascii_lowercase();
+    MOOD_EXPRESSIONS
+        .iter()
+        .find(|(_, moods)| moods.contains(&mood.as_str()))
+        .map(|(expression, _)| *expression)
+}
+
+/// Put on the `SceneRoot` of a `.vrm` file: once it loads, the file's
+/// `VrmModel` becomes the resource driving its expressions, and VRM 0.x
+/// models are turned to face +Z like 1.0 ones. Load the scene before the
+/// model, or a file without VRM data fails to load at all.
+#[derive(Component, Clone)]
+pub struct VrmScene {
+    /// The file's `VRM_MODEL_LABEL` asset
+    pub model: Handle<VrmModel>,
+    /// Replaces the model's default `retarget` when set
+    pub retarget: Option<RetargetMap>,
+}
+
+fn apply_vrm_scenes(
+    mut commands: Commands,
+    mut scenes: Query<(Entity, &VrmScene, &SceneRoot, &mut Transform)>,
+    models: Res<Assets<VrmModel>>,
+    asset_server: Res<AssetServer>,
+) {
+    for (entity, scene, root, mut transform) in &mut scenes {
+        match models.get(&scene.model) {
+            Some(model) => {
+                let mut model = model.clone();
+                log::info!(
+                    "Loaded a VRM {:?} model with {} expressions",
+                    model.version,
+                    model.expressions.len()
+                );
+                if model.version == VrmVersion::V0 {
+                    transform.rotate_y(PI);
+                }
+                if let Some(retarget) = &scene.retarget {
+                    model.retarget = retarget.clone();
+                }
+                commands.insert_resource(model);
+            }
+            // Labeled assets arrive along with the scene, so it has no VRM data
+            None if asset_server.is_loaded(&root.0) => {
+                log::warn!("{entity} has no VRM extension");
+            }
+            None if asset_server.load_state(&root.0).is_failed() => {}
+            None => continue,
+        }
+        commands.entity(entity).remove::<VrmScene>();
+    }
+}
+
+// The retarget binding and each node's morph binds, rebuilt when the model or rig changes
+struct ExpressionBinding {
+    generation: u32,
+    binding: MorphBinding,
+    node_binds: HashMap<String, Vec<(usize, usize, f32)>>,
+}
+
+fn drive_expressions(
+    vrm: Res<VrmModel>,
+    anim: Res<FacialAnim>,
+    mut targets: Query<(&Name, &mut MorphWeights)>,
+    mut bound: Local<Option<ExpressionBinding>>,
+) {
+    let Some(processed_data) = &anim.processed_data else {
+        return;
+    };
+
+    let generation = anim.retarget_generation();
+    if vrm.is_changed()
+        || bound
+            .as_ref()
+            .is_none_or(|bound| bound.generation != generation)
+    {
+        let names = vrm.expression_names();
+        for mapping in vrm.retarget.unresolved(&anim.rig, &names) {
+            log::debug!(
+                "Could not map {}/{} to VRM expression {}",
+                mapping.node,
+                mapping.channel,
+                mapping.morph
+            );
+        }
+        *bound = Some(ExpressionBinding {
+            generation,
+            binding: vrm.retarget.bind(&anim.rig, &names),
+            node_binds: vrm.node_binds(),
+        });
+    }
+    let Some(ExpressionBinding {
+        binding,
+        node_binds,
+        ..
+    }) = bound.as_ref()
+    else {
+        return;
+    };
+
+    let mut weights = binding.evaluate(processed_data);
+    let mood_index = anim
+        .mood
+        .as_deref()
+        .and_then(mood_expression)
+        .and_then(|expression| vrm.expression_index(expression));
+    if let Some(index) = mood_index {
+        weights[index] = weights[index].max(anim.intensity.clamp(0.0, 1.0));
+    }
+
+    for (name, mut morph_weights) in &mut targets {
+        let Some(binds) = node_binds.get(name.as_str()) else {
+            continue;
+        };
+
+        let morph_weights = morph_weights.weights_mut();
+        for &(_, morph, _) in binds {
+            if let Some(weight) = morph_weights.get_mut(morph) {
+                *weight = 0.0;
+            }
+        }
+        for &(expression, morph, bind_weight) in binds {
+            if let Some(weight) = morph_weights.get_mut(morph) {
+                *weight = (*weight + weights[expression] * bind_weight).min(1.0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn bind(node: usize, index: usize, weight: f32) -> MorphTargetBind {
+        MorphTargetBind {
+            node,
+            index,
+            weight,
+        }
+    }
+
+    #[test]
+    fn reads_preset_and_custom_v1_expressions() {
+        let vrm = json!({
+            "expressions": {
+                "preset": {
+                    "aa": {"morphTargetBinds": [{"node": 1, "index": 3, "weight": 0.5}]},
+                    "blink": {"morphTargetBinds": [{"node": 1, "index": 4}, {"node": 2}]},
+                },
+                "custom": {
+                    "smirk": {"morphTargetBinds": [{"node": 2, "index": 0, "weight": 0.75}]},
+                },
+            },
+        });
+        assert_eq!(
+            expressions_v1(&vrm),
+            [
+                Expression {
+                    name: "aa".to_string(),
+                    binds: vec![bind(1, 3, 0.5)],
+                },
+                Expression {
+                    name: "blink".to_string(),
+                    binds: vec![bind(1, 4, 1.0)],
+                },
+                Expression {
+                    name: "smirk".to_string(),
+                    binds: vec![bind(2, 0, 0.75)],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn expands_v0_mesh_binds_onto_their_nodes() {
+        let json = json!({
+            "nodes": [{"name": "Root"}, {"mesh": 0}, {"mesh": 1}, {"mesh": 0}],
+        });
+        let vrm = json!({
+            "blendShapeMaster": {"blendShapeGroups": [
+                {"presetName": "A", "binds": [{"mesh": 0, "index": 2, "weight": 50}]},
+                {"presetName": "Joy", "binds": [{"mesh": 1, "index": 1}]},
+                {"presetName": "unknown", "name": "Smirk", "binds": [{"mesh": 2, "index": 0}]},
+            ]},
+        });
+        assert_eq!(
+            expressions_v0(&vrm, &json),
+            [
+                Expression {
+                    name: "aa".to_string(),
+                    binds: vec![bind(1, 2, 0.5), bind(3, 2, 0.5)],
+                },
+                Expression {
+                    name: "happy".to_string(),
+                    binds: vec![bind(2, 1, 1.0)],
+                },
+                // No node shows mesh 2
+                Expression {
+                    name: "Smirk".to_string(),
+                    binds: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn renames_v0_presets() {
+        assert_eq!(preset_name_v0("a"), Some("aa"));
+        assert_eq!(preset_name_v0("Blink_L"), Some("blinkLeft"));
+        assert_eq!(preset_name_v0("SORROW"), Some("sad"));
+        assert_eq!(preset_name_v0("LookUp"), Some("lookUp"));
+        assert_eq!(preset_name_v0("unknown"), None);
+        assert_eq!(preset_name_v0(""), None);
+    }
+
+    #[test]
+    fn reads_a_document_by_version() {
+        let mut document = GltfDocument::new();
+        document.json["nodes"] = json!([{"name": "Face"}, {"mesh": 0}]);
+        assert!(VrmModel::from_document(&document).is_none());
+
+        document.json["extensions"] = json!({
+            "VRMC_vrm": {"expressions": {"preset": {
+                "ou": {"morphTargetBinds": [{"node": 0, "index": 1}]},
+            }}},
+        });
+        let model = VrmModel::from_document(&document).unwrap();
+        assert_eq!(model.version, VrmVersion::V1);
+        assert_eq!(model.node_names, ["Face", "GltfNode1"]);
+        assert_eq!(model.expression_index("ou"), Some(0));
+        assert_eq!(model.node_binds()["Face"], [(0, 1, 1.0)]);
+
+        document.json["extensions"] = json!({
+            "VRM": {"blendShapeMaster": {"blendShapeGroups": [
+                {"presetName": "u", "binds": [{"mesh": 0, "index": 1}]},
+            ]}},
+        });
+        let model = VrmModel::from_document(&document).unwrap();
+        assert_eq!(model.version, VrmVersion::V0);
+        assert_eq!(model.node_binds()["GltfNode1"], [(0, 1, 1.0)]);
+    }
+}